@@ -0,0 +1,10 @@
+-- Create event store table
+CREATE TABLE event_store (
+    position BIGSERIAL PRIMARY KEY,
+    stream_id VARCHAR(255) NOT NULL,
+    version BIGINT NOT NULL,
+    id UUID NOT NULL UNIQUE,
+    name VARCHAR(255) NOT NULL,
+    payload JSONB NOT NULL,
+    CONSTRAINT event_store_stream_version_key UNIQUE (stream_id, version)
+);
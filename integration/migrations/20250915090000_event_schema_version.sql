@@ -0,0 +1,2 @@
+-- Record the payload schema version of typed events
+ALTER TABLE events ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,6 @@
+-- Create snapshots table, holding the latest snapshot of each stream
+CREATE TABLE snapshots (
+    stream_id VARCHAR(255) PRIMARY KEY NOT NULL,
+    version BIGINT NOT NULL,
+    state JSONB NOT NULL
+);
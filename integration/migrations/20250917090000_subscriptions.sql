@@ -0,0 +1,7 @@
+-- Create subscriptions table, holding the checkpoint and lease of each projection
+CREATE TABLE subscriptions (
+    name VARCHAR(255) PRIMARY KEY NOT NULL,
+    position BIGINT NOT NULL DEFAULT 0,
+    lease_owner UUID,
+    lease_expires_at TIMESTAMPTZ
+);
@@ -0,0 +1,2 @@
+-- Record the state schema version of snapshots, so stale ones can be ignored
+ALTER TABLE snapshots ADD COLUMN state_version INTEGER NOT NULL DEFAULT 1;
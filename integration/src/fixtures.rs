@@ -0,0 +1,54 @@
+//! Loaders for the example repositories, keyed `users` and `events` in
+//! [`FixtureLoader`](tx_chainable::fixtures::FixtureLoader) documents.
+
+use crate::{Event, EventsRepository, User, UsersRepository};
+use sqlx::PgPool;
+use tx_chainable::fixtures::{Fixture, FixtureLoader, Loaded};
+use tx_chainable::{BoxFuture, TxError, TxRepositoryOf};
+
+/// A loader for both example repositories on `pool`.
+pub fn loader(pool: &PgPool) -> FixtureLoader {
+    FixtureLoader::new()
+        .fixture("users", UsersRepository::new(pool.clone()))
+        .fixture("events", EventsRepository::new(pool.clone()))
+}
+
+impl Fixture for UsersRepository<PgPool> {
+    type Repository = Self;
+    type Row = User;
+
+    fn repository(&self) -> &Self {
+        self
+    }
+
+    fn load<'tx>(
+        mut repository: TxRepositoryOf<'tx, Self>,
+        user: User,
+    ) -> BoxFuture<'tx, Result<Loaded<'tx, Self>, TxError>> {
+        Box::pin(async move {
+            let user = repository.create_user(user.id, user.name).await?;
+            Ok((repository, user))
+        })
+    }
+}
+
+impl Fixture for EventsRepository<PgPool> {
+    type Repository = Self;
+    type Row = Event;
+
+    fn repository(&self) -> &Self {
+        self
+    }
+
+    fn load<'tx>(
+        mut repository: TxRepositoryOf<'tx, Self>,
+        event: Event,
+    ) -> BoxFuture<'tx, Result<Loaded<'tx, Self>, TxError>> {
+        Box::pin(async move {
+            let event = repository
+                .create_event(event.id, event.name, event.payload)
+                .await?;
+            Ok((repository, event))
+        })
+    }
+}
@@ -1,4 +1,56 @@
+pub mod fixtures;
+pub mod participants;
+pub mod projections;
 pub mod repositories;
 
 // Re-export for convenient access
-pub use repositories::{Event, EventsRepository, User, UsersRepository};
+pub use repositories::{
+    Aggregate, DecodeEvent, Event, EventAppended, EventStoreError, EventStoreRepository, EventType,
+    EventTypeError, EventsRepository, ExpectedVersion, LoadedAggregate, MemoryEventsRepository,
+    MemoryUsersRepository, RecordedEvent, SnapshotPolicy, SnapshotRepository, SubscriptionError,
+    SubscriptionRepository, Upcasters, User, UsersError, UsersRepository,
+};
+
+/// Starts a throwaway Postgres with this crate's migrations applied, for
+/// tests that need a server of their own. It is stopped when the returned
+/// guard is dropped.
+#[cfg(feature = "test")]
+pub async fn embedded_database() -> std::io::Result<tx_chainable::embedded::EmbeddedPostgres> {
+    let server = tx_chainable::embedded::EmbeddedPostgres::start()?;
+    server
+        .migrate(&sqlx::migrate!("./migrations"))
+        .await
+        .map_err(std::io::Error::other)?;
+    Ok(server)
+}
+
+/// Points `DATABASE_URL` at an [`embedded_database`] when it is not set, once
+/// per test binary and before `#[sqlx::test]` reads it. The server lives as
+/// long as the process.
+///
+/// Constructors run before `main`, when no other thread can read the
+/// environment, so setting it here is sound.
+#[cfg(feature = "test")]
+#[ctor::ctor(unsafe)]
+fn provision_database_url() {
+    static SERVER: std::sync::OnceLock<tx_chainable::embedded::EmbeddedPostgres> =
+        std::sync::OnceLock::new();
+
+    if std::env::var_os("DATABASE_URL").is_some() {
+        return;
+    }
+    let server = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .and_then(|runtime| runtime.block_on(embedded_database()));
+    match server {
+        Ok(server) => {
+            std::env::set_var("DATABASE_URL", server.url());
+            let _ = SERVER.set(server);
+        }
+        // Tests then fail asking for DATABASE_URL
+        Err(error) => {
+            eprintln!("DATABASE_URL is not set and no embedded Postgres could start: {error}")
+        }
+    }
+}
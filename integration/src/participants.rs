@@ -0,0 +1,99 @@
+//! Generators plugging the example repositories into
+//! [`AtomicityHarness`](tx_chainable::atomicity::AtomicityHarness).
+
+use crate::{Event, EventsRepository, User, UsersRepository};
+use proptest::prelude::*;
+use proptest::strategy::BoxedStrategy;
+use sqlx::PgPool;
+use tx_chainable::atomicity::Participant;
+use tx_chainable::{BoxFuture, TxError, TxRepositoryOf};
+use uuid::Uuid;
+
+/// Random ids, not shrunk so that the writes of a shrunk plan stay distinct.
+fn ids() -> impl Strategy<Value = Uuid> {
+    any::<u128>().prop_map(Uuid::from_u128).no_shrink()
+}
+
+impl Participant for UsersRepository<PgPool> {
+    type Repository = Self;
+    type Write = User;
+
+    fn repository(&self) -> &Self {
+        self
+    }
+
+    fn writes(&self) -> BoxedStrategy<User> {
+        (ids(), "[a-z]{1,8}")
+            .prop_map(|(id, name)| User { id, name })
+            .boxed()
+    }
+
+    fn write<'tx>(
+        mut repository: TxRepositoryOf<'tx, Self>,
+        user: User,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self>, TxError>> {
+        Box::pin(async move {
+            repository.create_user(user.id, user.name).await?;
+            Ok(repository)
+        })
+    }
+
+    fn is_visible<'a>(&'a self, user: &'a User) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        Box::pin(async move {
+            let users = self.clone().get_users(i64::MAX).await?;
+            Ok(users.contains(user))
+        })
+    }
+
+    fn remove<'a>(&'a self, user: &'a User) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            self.clone().delete_user(user.id).await?;
+            Ok(())
+        })
+    }
+}
+
+impl Participant for EventsRepository<PgPool> {
+    type Repository = Self;
+    type Write = Event;
+
+    fn repository(&self) -> &Self {
+        self
+    }
+
+    fn writes(&self) -> BoxedStrategy<Event> {
+        (ids(), "[a-z]{1,8}", any::<i32>())
+            .prop_map(|(id, name, value)| Event {
+                id,
+                name,
+                payload: serde_json::json!({ "value": value }),
+            })
+            .boxed()
+    }
+
+    fn write<'tx>(
+        mut repository: TxRepositoryOf<'tx, Self>,
+        event: Event,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self>, TxError>> {
+        Box::pin(async move {
+            repository
+                .create_event(event.id, event.name, event.payload)
+                .await?;
+            Ok(repository)
+        })
+    }
+
+    fn is_visible<'a>(&'a self, event: &'a Event) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        Box::pin(async move {
+            let events = self.clone().get_events(i64::MAX).await?;
+            Ok(events.contains(event))
+        })
+    }
+
+    fn remove<'a>(&'a self, event: &'a Event) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            self.clone().delete_event(event.id).await?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,267 @@
+//! Projections of the event store into read models.
+//!
+//! A [`ProjectionRunner`] reads [`EventStoreRepository`] events in global
+//! order and hands them to a [`Projection`] in batches. Each batch is one
+//! `begin`: the projection's writes and the subscription's new checkpoint
+//! commit together, so every event is applied exactly once. A lease on the
+//! subscription lets several runners of the same projection, e.g. one per
+//! process, run side by side while only one of them advances it.
+
+use crate::{
+    EventStoreRepository, RecordedEvent, SubscriptionError, SubscriptionRepository, User,
+    UsersRepository,
+};
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tx_chainable::{Begin, BoxFuture, Chainable, Tx, TxError, TxRepositoryOf};
+use uuid::Uuid;
+
+/// A read model kept up to date from the event store.
+pub trait Projection: Send + Sync + 'static {
+    type Repository: Tx + Send + Sync + 'static;
+
+    /// The subscription holding this projection's checkpoint and lease.
+    fn name(&self) -> &str;
+
+    fn repository(&self) -> &Self::Repository;
+
+    fn apply<'tx>(
+        repository: TxRepositoryOf<'tx, Self::Repository>,
+        event: RecordedEvent,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self::Repository>, TxError>>;
+
+    /// Clears the read model before [`ProjectionRunner::rebuild`] replays
+    /// every event.
+    fn reset<'tx>(
+        repository: TxRepositoryOf<'tx, Self::Repository>,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self::Repository>, TxError>>;
+}
+
+/// Runs one projection, as one lease owner of its subscription.
+pub struct ProjectionRunner<P> {
+    projection: P,
+    subscriptions: SubscriptionRepository<PgPool>,
+    events: EventStoreRepository<PgPool>,
+    owner: Uuid,
+    lease_ttl: Duration,
+    batch_size: i64,
+    poll_interval: Duration,
+}
+
+impl<P> ProjectionRunner<P>
+where
+    P: Projection,
+    for<'tx> TxRepositoryOf<'tx, P::Repository>: Send,
+{
+    pub fn new(pool: PgPool, projection: P) -> Self {
+        Self {
+            projection,
+            subscriptions: SubscriptionRepository::new(pool.clone()),
+            events: EventStoreRepository::new(pool),
+            owner: Uuid::new_v4(),
+            lease_ttl: Duration::from_secs(30),
+            batch_size: 100,
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+
+    /// How long the lease lasts without being renewed. Every batch renews
+    /// it. Defaults to 30s.
+    pub fn lease_ttl(mut self, ttl: Duration) -> Self {
+        self.lease_ttl = ttl;
+        self
+    }
+
+    /// Events applied per transaction. Defaults to 100.
+    pub fn batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// How often [`run`](Self::run) looks for new events. Defaults to 1s.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// The lease owner this runner acts as.
+    pub fn owner(&self) -> Uuid {
+        self.owner
+    }
+
+    /// Applies batches until no event is left, returning how many events
+    /// were applied, or `None` if another runner holds the lease.
+    pub async fn catch_up(&self) -> Result<Option<usize>, TxError> {
+        if !self.acquire_lease().await? {
+            return Ok(None);
+        }
+        let mut applied = 0;
+        loop {
+            match self.apply_batch().await? {
+                0 => return Ok(Some(applied)),
+                batch => applied += batch,
+            }
+        }
+    }
+
+    /// Resets the read model and the checkpoint in one transaction, then
+    /// replays every event. Returns `None` if another runner holds the lease.
+    pub async fn rebuild(&self) -> Result<Option<usize>, TxError> {
+        if !self.acquire_lease().await? {
+            return Ok(None);
+        }
+        let name = self.projection.name();
+        let (owner, ttl) = (self.owner, self.lease_ttl);
+        self.subscriptions
+            .begin(|mut subscriptions| {
+                Box::pin(async move {
+                    subscriptions.lock(name, owner, ttl).await?;
+                    let mut subscriptions = subscriptions
+                        .chain(self.projection.repository(), P::reset)
+                        .await?;
+                    subscriptions.save_checkpoint(name, 0).await?;
+                    Ok(subscriptions)
+                })
+            })
+            .await?;
+        self.catch_up().await
+    }
+
+    /// Catches up every poll interval until `stop` resolves, then releases
+    /// the lease. A runner that loses its lease keeps polling to take it
+    /// back once it is free.
+    pub async fn run(&self, stop: impl Future<Output = ()>) -> Result<(), TxError> {
+        tokio::pin!(stop);
+        loop {
+            match self.catch_up().await {
+                Ok(_) => {}
+                Err(error) if error.domain::<SubscriptionError>().is_some() => {}
+                Err(error) => return Err(error),
+            }
+            tokio::select! {
+                () = &mut stop => break,
+                () = tokio::time::sleep(self.poll_interval) => {}
+            }
+        }
+        self.subscriptions
+            .clone()
+            .release_lease(self.projection.name(), self.owner)
+            .await?;
+        Ok(())
+    }
+
+    async fn acquire_lease(&self) -> Result<bool, sqlx::Error> {
+        self.subscriptions
+            .clone()
+            .acquire_lease(self.projection.name(), self.owner, self.lease_ttl)
+            .await
+    }
+
+    /// Applies the events after the checkpoint, up to the batch size, and
+    /// moves the checkpoint past them in the same transaction.
+    async fn apply_batch(&self) -> Result<usize, TxError> {
+        let name = self.projection.name();
+        let (owner, ttl, batch_size) = (self.owner, self.lease_ttl, self.batch_size);
+        let applied = AtomicUsize::new(0);
+        let applied_ref = &applied;
+        self.subscriptions
+            .begin(|mut subscriptions| {
+                Box::pin(async move {
+                    let checkpoint = subscriptions.lock(name, owner, ttl).await?;
+                    subscriptions
+                        .chain(&self.events, |mut events| {
+                            Box::pin(async move {
+                                let batch = events.read_all(checkpoint, batch_size).await?;
+                                let Some(last) = batch.last().map(|event| event.position) else {
+                                    return Ok(events);
+                                };
+                                applied_ref.store(batch.len(), Ordering::Relaxed);
+                                let events = events
+                                    .chain(self.projection.repository(), |mut repository| {
+                                        Box::pin(async move {
+                                            for event in batch {
+                                                repository = P::apply(repository, event).await?;
+                                            }
+                                            Ok(repository)
+                                        })
+                                    })
+                                    .await?;
+                                events
+                                    .chain(&self.subscriptions, |mut subscriptions| {
+                                        Box::pin(async move {
+                                            subscriptions.save_checkpoint(name, last).await?;
+                                            Ok(subscriptions)
+                                        })
+                                    })
+                                    .await
+                            })
+                        })
+                        .await
+                })
+            })
+            .await?;
+        Ok(applied.into_inner())
+    }
+}
+
+/// Projects `user_registered` and `user_renamed` events, whose payload is a
+/// [`User`], into the users table.
+#[derive(Clone)]
+pub struct UserDirectory {
+    users: UsersRepository<PgPool>,
+}
+
+impl UserDirectory {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            users: UsersRepository::new(pool),
+        }
+    }
+}
+
+impl Projection for UserDirectory {
+    type Repository = UsersRepository<PgPool>;
+
+    fn name(&self) -> &str {
+        "user_directory"
+    }
+
+    fn repository(&self) -> &UsersRepository<PgPool> {
+        &self.users
+    }
+
+    fn apply<'tx>(
+        mut users: TxRepositoryOf<'tx, Self::Repository>,
+        event: RecordedEvent,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self::Repository>, TxError>> {
+        Box::pin(async move {
+            let user = || {
+                serde_json::from_value::<User>(event.payload.clone())
+                    .map_err(|error| TxError::Domain(Box::new(error)))
+            };
+            match event.name.as_str() {
+                "user_registered" => {
+                    let user = user()?;
+                    users.create_user(user.id, user.name).await?;
+                }
+                "user_renamed" => {
+                    let user = user()?;
+                    users.rename_user(user.id, user.name).await?;
+                }
+                _ => {}
+            }
+            Ok(users)
+        })
+    }
+
+    fn reset<'tx>(
+        mut users: TxRepositoryOf<'tx, Self::Repository>,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self::Repository>, TxError>> {
+        Box::pin(async move {
+            users.delete_users().await?;
+            Ok(users)
+        })
+    }
+}
@@ -0,0 +1,32 @@
+use crate::repositories::event_store::models::ExpectedVersion;
+use std::fmt;
+
+/// Concurrency conflicts of [`EventStoreRepository::append`](crate::EventStoreRepository::append).
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventStoreError {
+    WrongExpectedVersion {
+        stream_id: String,
+        expected: ExpectedVersion,
+        actual: i64,
+    },
+    /// A concurrent transaction appended the same stream version first.
+    ConcurrentAppend,
+}
+
+impl fmt::Display for EventStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongExpectedVersion {
+                stream_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "stream {stream_id} is at version {actual}, expected {expected}"
+            ),
+            Self::ConcurrentAppend => f.write_str("stream was appended to concurrently"),
+        }
+    }
+}
+
+impl std::error::Error for EventStoreError {}
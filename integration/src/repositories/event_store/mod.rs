@@ -0,0 +1,7 @@
+pub mod errors;
+pub mod models;
+pub mod repository;
+
+pub use errors::*;
+pub use models::*;
+pub use repository::*;
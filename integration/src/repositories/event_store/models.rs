@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use uuid::Uuid;
+
+/// An event as stored in a stream.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Position in the global sequence, across all streams.
+    pub position: i64,
+    pub stream_id: String,
+    /// Version of the stream after this event; the first event is version 1.
+    pub version: i64,
+    pub id: Uuid,
+    pub name: String,
+    pub payload: Value,
+}
+
+/// Notification sent on [`EventAppended::CHANNEL`] when an append commits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventAppended {
+    pub stream_id: String,
+    /// Version of the stream after the append.
+    pub version: i64,
+    /// Position of the last appended event.
+    pub position: i64,
+}
+
+impl EventAppended {
+    pub const CHANNEL: &'static str = "event_store";
+}
+
+/// The version a stream must be at for an append to succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedVersion {
+    Any,
+    /// The stream has no events yet.
+    NoStream,
+    Exact(i64),
+}
+
+impl ExpectedVersion {
+    pub fn matches(self, actual: i64) -> bool {
+        match self {
+            Self::Any => true,
+            Self::NoStream => actual == 0,
+            Self::Exact(version) => actual == version,
+        }
+    }
+}
+
+impl fmt::Display for ExpectedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("any version"),
+            Self::NoStream => f.write_str("no stream"),
+            Self::Exact(version) => write!(f, "version {version}"),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::repositories::event_store::errors::EventStoreError;
+use crate::repositories::event_store::models::{EventAppended, ExpectedVersion, RecordedEvent};
+use crate::repositories::events::models::Event;
+use sqlx::PgPool;
+use std::sync::LazyLock;
+#[cfg(feature = "test")]
+use tx_chainable::testing::FromTestPool;
+use tx_chainable::{
+    ConstraintRegistry, Execute, GetExecutor, Transaction, Tx, TxCoordinator, TxError,
+};
+
+pub(crate) static CONSTRAINTS: LazyLock<ConstraintRegistry> = LazyLock::new(|| {
+    ConstraintRegistry::new().map("event_store_stream_version_key", |_| {
+        EventStoreError::ConcurrentAppend
+    })
+});
+
+/// Advisory lock held by appending transactions until they end, so that
+/// global positions are committed in order.
+const APPEND_LOCK: i64 = 0x6576_656e_7473;
+
+/// Append-only event streams with a global sequence.
+///
+/// Appends take a transaction-wide lock, so that an event never commits
+/// after one with a higher position: readers following
+/// [`read_all`](Self::read_all) never skip an event. Appending transactions
+/// are serialized by it, so keep them short.
+#[derive(Clone)]
+pub struct EventStoreRepository<E: Execute> {
+    executor: E,
+    coordinator: Option<TxCoordinator>,
+}
+
+impl<E: Execute> Tx for EventStoreRepository<E> {
+    type TxRepository<'tx> = EventStoreRepository<Transaction<'tx>>;
+
+    fn constraints() -> &'static ConstraintRegistry {
+        &CONSTRAINTS
+    }
+}
+
+impl<'tx> GetExecutor<'tx> for EventStoreRepository<PgPool> {
+    type Executor = &'tx PgPool;
+    fn get_executor(&'tx self) -> Self::Executor {
+        &self.executor
+    }
+
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        self.coordinator.as_ref()
+    }
+}
+
+impl<'tx> From<EventStoreRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: EventStoreRepository<Transaction<'tx>>) -> Self {
+        repository.executor
+    }
+}
+
+impl<'tx> From<Transaction<'tx>> for EventStoreRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self {
+            executor: tx,
+            coordinator: None,
+        }
+    }
+}
+
+impl EventStoreRepository<PgPool> {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            executor: pool,
+            coordinator: None,
+        }
+    }
+
+    /// Routes `begin` through `coordinator`.
+    pub fn with_coordinator(mut self, coordinator: TxCoordinator) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+}
+
+#[cfg(feature = "test")]
+impl FromTestPool for EventStoreRepository<PgPool> {
+    fn from_test_pool(pool: &PgPool) -> Self {
+        Self::new(pool.clone())
+    }
+}
+
+impl EventStoreRepository<Transaction<'_>> {
+    /// Appends `events` to a stream, failing with an [`EventStoreError`] if
+    /// the stream is not at the `expected` version. Listeners on
+    /// [`EventAppended::CHANNEL`] are notified once the transaction commits.
+    pub async fn append(
+        &mut self,
+        stream_id: &str,
+        expected: ExpectedVersion,
+        events: Vec<Event>,
+    ) -> Result<Vec<RecordedEvent>, TxError> {
+        self.executor
+            .execute(|e| {
+                sqlx::query("SELECT pg_advisory_xact_lock($1)")
+                    .bind(APPEND_LOCK)
+                    .execute(e)
+            })
+            .await?;
+        let actual = self.stream_version(stream_id).await?;
+        if !expected.matches(actual) {
+            return Err(TxError::Domain(Box::new(
+                EventStoreError::WrongExpectedVersion {
+                    stream_id: stream_id.to_string(),
+                    expected,
+                    actual,
+                },
+            )));
+        }
+        if events.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut ids = Vec::with_capacity(events.len());
+        let mut names = Vec::with_capacity(events.len());
+        let mut payloads = Vec::with_capacity(events.len());
+        for event in events {
+            ids.push(event.id);
+            names.push(event.name);
+            payloads.push(event.payload);
+        }
+        let mut recorded = self
+            .executor
+            .execute(|e| {
+                sqlx::query_as::<_, RecordedEvent>(
+                    "INSERT INTO event_store (stream_id, version, id, name, payload) \
+                     SELECT $1, $2 + n, id, name, payload \
+                     FROM UNNEST($3::uuid[], $4::text[], $5::jsonb[]) \
+                     WITH ORDINALITY AS e(id, name, payload, n) ORDER BY n \
+                     RETURNING position, stream_id, version, id, name, payload",
+                )
+                .bind(stream_id)
+                .bind(actual)
+                .bind(ids)
+                .bind(names)
+                .bind(payloads)
+                .fetch_all(e)
+            })
+            .await?;
+        recorded.sort_by_key(|event| event.version);
+
+        let last = &recorded[recorded.len() - 1];
+        let appended = serde_json::to_string(&EventAppended {
+            stream_id: stream_id.to_string(),
+            version: last.version,
+            position: last.position,
+        })
+        .map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        self.executor
+            .notify(EventAppended::CHANNEL, &appended)
+            .await?;
+        Ok(recorded)
+    }
+}
+
+impl<E: Execute> EventStoreRepository<E> {
+    /// The version of the last event in a stream, 0 if it has none.
+    pub async fn stream_version(&mut self, stream_id: &str) -> Result<i64, sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query_scalar(
+                    "SELECT COALESCE(MAX(version), 0) FROM event_store WHERE stream_id = $1",
+                )
+                .bind(stream_id)
+                .fetch_one(e)
+            })
+            .await
+    }
+
+    /// Events of a stream after `after_version` in version order; 0 reads
+    /// from the start.
+    pub async fn read_stream(
+        &mut self,
+        stream_id: &str,
+        after_version: i64,
+        limit: i64,
+    ) -> Result<Vec<RecordedEvent>, sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query_as::<_, RecordedEvent>(
+                    "SELECT position, stream_id, version, id, name, payload FROM event_store \
+                     WHERE stream_id = $1 AND version > $2 ORDER BY version LIMIT $3",
+                )
+                .bind(stream_id)
+                .bind(after_version)
+                .bind(limit)
+                .fetch_all(e)
+            })
+            .await
+    }
+
+    /// Events of all streams after `after_position` in global order; 0 reads
+    /// from the start.
+    pub async fn read_all(
+        &mut self,
+        after_position: i64,
+        limit: i64,
+    ) -> Result<Vec<RecordedEvent>, sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query_as::<_, RecordedEvent>(
+                    "SELECT position, stream_id, version, id, name, payload FROM event_store \
+                     WHERE position > $1 ORDER BY position LIMIT $2",
+                )
+                .bind(after_position)
+                .bind(limit)
+                .fetch_all(e)
+            })
+            .await
+    }
+}
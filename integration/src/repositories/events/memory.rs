@@ -0,0 +1,68 @@
+use crate::repositories::events::models::Event;
+use std::collections::BTreeMap;
+use tx_chainable::{GetExecutor, MemoryError, MemoryExecute, MemoryStore, Transaction, Tx};
+use uuid::Uuid;
+
+const TABLE: &str = "events";
+
+/// [`EventsRepository`](crate::EventsRepository) backed by a [`MemoryStore`].
+#[derive(Clone)]
+pub struct MemoryEventsRepository<E: MemoryExecute> {
+    executor: E,
+}
+
+impl<E: MemoryExecute> Tx for MemoryEventsRepository<E> {
+    type TxRepository<'tx> = MemoryEventsRepository<Transaction<'tx>>;
+}
+
+impl<'tx> GetExecutor<'tx> for MemoryEventsRepository<MemoryStore> {
+    type Executor = &'tx MemoryStore;
+    fn get_executor(&'tx self) -> Self::Executor {
+        &self.executor
+    }
+}
+
+impl<'tx> From<MemoryEventsRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: MemoryEventsRepository<Transaction<'tx>>) -> Self {
+        repository.executor
+    }
+}
+
+impl<'tx> From<Transaction<'tx>> for MemoryEventsRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self { executor: tx }
+    }
+}
+
+impl MemoryEventsRepository<MemoryStore> {
+    pub fn new(store: MemoryStore) -> Self {
+        Self { executor: store }
+    }
+}
+
+impl<E: MemoryExecute> MemoryEventsRepository<E> {
+    pub async fn get_events(&mut self, limit: i64) -> Result<Vec<Event>, sqlx::Error> {
+        self.executor.read(TABLE, |events: &BTreeMap<Uuid, Event>| {
+            let mut events: Vec<_> = events.values().cloned().collect();
+            events.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+            events.truncate(limit.max(0) as usize);
+            events
+        })
+    }
+
+    pub async fn create_event(
+        &mut self,
+        id: Uuid,
+        name: String,
+        payload: serde_json::Value,
+    ) -> Result<Event, sqlx::Error> {
+        self.executor.write(TABLE, |events| {
+            if events.contains_key(&id) {
+                return Err(MemoryError::unique_violation("events_pkey").into());
+            }
+            let event = Event { id, name, payload };
+            events.insert(id, event.clone());
+            Ok(event)
+        })?
+    }
+}
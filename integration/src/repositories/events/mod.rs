@@ -1,5 +1,9 @@
+pub mod memory;
 pub mod models;
 pub mod repository;
+pub mod typed;
 
+pub use memory::*;
 pub use models::*;
 pub use repository::*;
+pub use typed::*;
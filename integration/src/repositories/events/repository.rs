@@ -1,15 +1,19 @@
 use crate::repositories::events::models::Event;
-use sqlx::{PgPool, PgTransaction};
-use tx_chainable::{Execute, Tx, GetExecutor};
+use crate::repositories::events::typed::{DecodeEvent, EventType};
+use sqlx::PgPool;
+#[cfg(feature = "test")]
+use tx_chainable::testing::FromTestPool;
+use tx_chainable::{Execute, GetExecutor, Transaction, Tx, TxCoordinator, TxError};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct EventsRepository<E: Execute> {
     executor: E,
+    coordinator: Option<TxCoordinator>,
 }
 
 impl<E: Execute> Tx for EventsRepository<E> {
-    type TxRepository<'tx> = EventsRepository<PgTransaction<'tx>>;
+    type TxRepository<'tx> = EventsRepository<Transaction<'tx>>;
 }
 
 impl<'tx> GetExecutor<'tx> for EventsRepository<PgPool> {
@@ -17,23 +21,54 @@ impl<'tx> GetExecutor<'tx> for EventsRepository<PgPool> {
     fn get_executor(&'tx self) -> Self::Executor {
         &self.executor
     }
+
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        self.coordinator.as_ref()
+    }
 }
 
-impl<'tx> Into<PgTransaction<'tx>> for EventsRepository<PgTransaction<'tx>> {
-    fn into(self) -> PgTransaction<'tx> {
-        self.executor
+impl<'tx> From<EventsRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: EventsRepository<Transaction<'tx>>) -> Self {
+        repository.executor
     }
 }
 
-impl<'tx> From<PgTransaction<'tx>> for EventsRepository<PgTransaction<'tx>> {
-    fn from(tx: PgTransaction<'tx>) -> Self {
-        Self { executor: tx }
+impl<'tx> From<Transaction<'tx>> for EventsRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self {
+            executor: tx,
+            coordinator: None,
+        }
     }
 }
 
 impl EventsRepository<PgPool> {
     pub fn new(pool: PgPool) -> Self {
-        Self { executor: pool }
+        Self {
+            executor: pool,
+            coordinator: None,
+        }
+    }
+
+    /// Routes `begin` through `coordinator`.
+    pub fn with_coordinator(mut self, coordinator: TxCoordinator) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+}
+
+impl EventsRepository<Transaction<'_>> {
+    /// Whether events written here will be rolled back, so consumers must not
+    /// be notified of them.
+    pub fn is_dry_run(&self) -> bool {
+        self.executor.context().is_dry_run()
+    }
+}
+
+#[cfg(feature = "test")]
+impl FromTestPool for EventsRepository<PgPool> {
+    fn from_test_pool(pool: &PgPool) -> Self {
+        Self::new(pool.clone())
     }
 }
 
@@ -42,7 +77,7 @@ impl<E: Execute> EventsRepository<E> {
         self.executor
             .execute(|e| {
                 sqlx::query_as::<_, Event>(
-                    "SELECT id, name, payload FROM events ORDER BY name, id LIMIT $1"
+                    "SELECT id, name, payload FROM events ORDER BY name, id LIMIT $1",
                 )
                 .bind(limit)
                 .fetch_all(e)
@@ -50,17 +85,105 @@ impl<E: Execute> EventsRepository<E> {
             .await
     }
 
-    pub async fn create_event(&mut self, id: Uuid, name: String, payload: serde_json::Value) -> Result<Event, sqlx::Error> {
+    pub async fn create_event(
+        &mut self,
+        id: Uuid,
+        name: String,
+        payload: serde_json::Value,
+    ) -> Result<Event, sqlx::Error> {
         self.executor
             .execute(|e| {
                 sqlx::query_as::<_, Event>(
                     "INSERT INTO events (id, name, payload) VALUES ($1, $2, $3) RETURNING id, name, payload"
                 )
-                .bind(&id)
-                .bind(&name)
-                .bind(&payload)
+                .bind(id)
+                .bind(name)
+                .bind(payload)
                 .fetch_one(e)
             })
             .await
     }
+
+    /// Returns whether there was an event with `id`.
+    pub async fn delete_event(&mut self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = self
+            .executor
+            .execute(|e| {
+                sqlx::query("DELETE FROM events WHERE id = $1")
+                    .bind(id)
+                    .execute(e)
+            })
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Stores `event` under its type name and current schema version.
+    pub async fn create_typed<T: EventType>(
+        &mut self,
+        id: Uuid,
+        event: &T,
+    ) -> Result<Event, sqlx::Error> {
+        let payload = serde_json::to_value(event).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        self.executor
+            .execute(|e| {
+                sqlx::query_as::<_, Event>(
+                    "INSERT INTO events (id, name, payload, schema_version) VALUES ($1, $2, $3, $4) RETURNING id, name, payload"
+                )
+                .bind(id)
+                .bind(T::TYPE)
+                .bind(payload)
+                .bind(T::VERSION)
+                .fetch_one(e)
+            })
+            .await
+    }
+
+    /// Reads an event as `T`, upcasting older payloads. Events `T` cannot
+    /// decode fail with an [`EventTypeError`](crate::EventTypeError).
+    pub async fn get_typed<T: DecodeEvent>(&mut self, id: Uuid) -> Result<Option<T>, TxError> {
+        let stored = self
+            .executor
+            .execute(|e| {
+                sqlx::query_as::<_, StoredEvent>(
+                    "SELECT name, schema_version, payload FROM events WHERE id = $1",
+                )
+                .bind(id)
+                .fetch_optional(e)
+            })
+            .await?;
+        stored.map(StoredEvent::decode).transpose()
+    }
+
+    /// Reads events as `T` in the order of `get_events`, failing on the first
+    /// event `T` cannot decode.
+    pub async fn get_typed_events<T: DecodeEvent>(
+        &mut self,
+        limit: i64,
+    ) -> Result<Vec<T>, TxError> {
+        let stored = self
+            .executor
+            .execute(|e| {
+                sqlx::query_as::<_, StoredEvent>(
+                    "SELECT name, schema_version, payload FROM events ORDER BY name, id LIMIT $1",
+                )
+                .bind(limit)
+                .fetch_all(e)
+            })
+            .await?;
+        stored.into_iter().map(StoredEvent::decode).collect()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredEvent {
+    name: String,
+    schema_version: i32,
+    payload: serde_json::Value,
+}
+
+impl StoredEvent {
+    fn decode<T: DecodeEvent>(self) -> Result<T, TxError> {
+        T::decode(&self.name, self.schema_version, self.payload)
+            .map_err(|error| TxError::Domain(Box::new(error)))
+    }
 }
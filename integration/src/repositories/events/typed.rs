@@ -0,0 +1,168 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use tx_chainable::BoxDynError;
+
+/// A typed event payload, stored under its type name and schema version.
+pub trait EventType: Serialize + DeserializeOwned {
+    /// The name events of this type are stored under.
+    const TYPE: &'static str;
+    /// The schema version of this struct; start at 1 and bump it with each
+    /// upcaster added.
+    const VERSION: i32 = 1;
+
+    /// Migrations of payloads stored with older schema versions.
+    fn upcasters() -> &'static Upcasters {
+        &NO_UPCASTERS
+    }
+}
+
+static NO_UPCASTERS: Upcasters = Upcasters::new();
+
+type Upcaster = Box<dyn Fn(Value) -> Result<Value, BoxDynError> + Send + Sync>;
+
+/// Migrates payloads from older schema versions, one version at a time.
+///
+/// ```ignore
+/// static UPCASTERS: LazyLock<Upcasters> = LazyLock::new(|| {
+///     Upcasters::new().from(1, |mut payload| {
+///         payload["currency"] = "EUR".into();
+///         Ok(payload)
+///     })
+/// });
+/// ```
+#[derive(Default)]
+pub struct Upcasters {
+    upcasters: Vec<(i32, Upcaster)>,
+}
+
+impl Upcasters {
+    pub const fn new() -> Self {
+        Self {
+            upcasters: Vec::new(),
+        }
+    }
+
+    /// Registers the migration of payloads at `version` to `version + 1`.
+    pub fn from<F>(mut self, version: i32, f: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value, BoxDynError> + Send + Sync + 'static,
+    {
+        self.upcasters.push((version, Box::new(f)));
+        self
+    }
+
+    fn get(&self, version: i32) -> Option<&Upcaster> {
+        self.upcasters
+            .iter()
+            .find(|(from, _)| *from == version)
+            .map(|(_, f)| f)
+    }
+}
+
+impl fmt::Debug for Upcasters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.upcasters.iter().map(|(version, _)| version))
+            .finish()
+    }
+}
+
+/// Types stored events can be read as: every [`EventType`], and enums over
+/// several of them that dispatch on the type name.
+pub trait DecodeEvent: Sized {
+    fn decode(name: &str, version: i32, payload: Value) -> Result<Self, EventTypeError>;
+}
+
+impl<T: EventType> DecodeEvent for T {
+    fn decode(name: &str, version: i32, payload: Value) -> Result<Self, EventTypeError> {
+        if name != T::TYPE {
+            return Err(EventTypeError::UnknownType {
+                name: name.to_string(),
+            });
+        }
+        if version > T::VERSION {
+            return Err(EventTypeError::UnsupportedVersion {
+                name: name.to_string(),
+                version,
+            });
+        }
+        let mut payload = payload;
+        for version in version..T::VERSION {
+            let upcaster =
+                T::upcasters()
+                    .get(version)
+                    .ok_or_else(|| EventTypeError::MissingUpcaster {
+                        name: name.to_string(),
+                        version,
+                    })?;
+            payload = upcaster(payload).map_err(|source| EventTypeError::Upcast {
+                name: name.to_string(),
+                version,
+                source,
+            })?;
+        }
+        serde_json::from_value(payload).map_err(|source| EventTypeError::Payload {
+            name: name.to_string(),
+            source,
+        })
+    }
+}
+
+/// Failures reading a stored event as a typed event.
+#[derive(Debug)]
+pub enum EventTypeError {
+    /// The event's type name is not one the reader decodes.
+    UnknownType { name: String },
+    /// The event was written with a newer schema than the reader knows.
+    UnsupportedVersion { name: String, version: i32 },
+    /// No upcaster migrates payloads from `version`.
+    MissingUpcaster { name: String, version: i32 },
+    /// The upcaster from `version` failed.
+    Upcast {
+        name: String,
+        version: i32,
+        source: BoxDynError,
+    },
+    /// The upcast payload does not deserialize into the current struct.
+    Payload {
+        name: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for EventTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownType { name } => write!(f, "unknown event type {name}"),
+            Self::UnsupportedVersion { name, version } => {
+                write!(f, "{name} schema version {version} is newer than supported")
+            }
+            Self::MissingUpcaster { name, version } => {
+                write!(f, "no upcaster for {name} schema version {version}")
+            }
+            Self::Upcast {
+                name,
+                version,
+                source,
+            } => write!(
+                f,
+                "upcasting {name} from schema version {version} failed: {source}"
+            ),
+            Self::Payload { name, source } => write!(f, "invalid {name} payload: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for EventTypeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Upcast { source, .. } => Some(source.as_ref()),
+            Self::Payload { source, .. } => Some(source),
+            Self::UnknownType { .. }
+            | Self::UnsupportedVersion { .. }
+            | Self::MissingUpcaster { .. } => None,
+        }
+    }
+}
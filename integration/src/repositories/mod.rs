@@ -1,5 +1,16 @@
+pub mod event_store;
 pub mod events;
+pub mod snapshots;
+pub mod subscriptions;
 pub mod users;
 
-pub use events::{Event, EventsRepository};
-pub use users::{User, UsersRepository};
\ No newline at end of file
+pub use event_store::{
+    EventAppended, EventStoreError, EventStoreRepository, ExpectedVersion, RecordedEvent,
+};
+pub use events::{
+    DecodeEvent, Event, EventType, EventTypeError, EventsRepository, MemoryEventsRepository,
+    Upcasters,
+};
+pub use snapshots::{Aggregate, LoadedAggregate, SnapshotPolicy, SnapshotRepository};
+pub use subscriptions::{SubscriptionError, SubscriptionRepository};
+pub use users::{MemoryUsersRepository, User, UsersError, UsersRepository};
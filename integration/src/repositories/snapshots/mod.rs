@@ -0,0 +1,5 @@
+pub mod models;
+pub mod repository;
+
+pub use models::*;
+pub use repository::*;
@@ -0,0 +1,58 @@
+use crate::repositories::event_store::models::RecordedEvent;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// State rebuilt from the events of one stream.
+pub trait Aggregate: Default + Serialize + DeserializeOwned + Send {
+    /// The schema version of the state as snapshotted; bump it when the state
+    /// changes shape, so older snapshots are ignored and the stream is
+    /// replayed instead.
+    const STATE_VERSION: i32 = 1;
+
+    fn apply(&mut self, event: &RecordedEvent);
+}
+
+/// When [`SnapshotRepository::save_if_due`](crate::SnapshotRepository::save_if_due)
+/// stores a snapshot. Defaults to every 100 events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotPolicy {
+    Never,
+    /// Once this many events were applied since the last snapshot.
+    Every(i64),
+}
+
+impl Default for SnapshotPolicy {
+    fn default() -> Self {
+        Self::Every(100)
+    }
+}
+
+impl SnapshotPolicy {
+    pub fn is_due(self, snapshot_version: i64, version: i64) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Every(events) => version - snapshot_version >= events,
+        }
+    }
+}
+
+/// An aggregate at a stream version, and the version of the snapshot it was
+/// loaded from (0 if none).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedAggregate<A> {
+    pub stream_id: String,
+    pub state: A,
+    pub version: i64,
+    pub snapshot_version: i64,
+}
+
+impl<A: Aggregate> LoadedAggregate<A> {
+    /// Applies events appended after loading, such as those returned by
+    /// [`EventStoreRepository::append`](crate::EventStoreRepository::append).
+    pub fn apply(&mut self, events: &[RecordedEvent]) {
+        for event in events {
+            self.state.apply(event);
+            self.version = event.version;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::repositories::event_store::models::RecordedEvent;
+use crate::repositories::snapshots::models::{Aggregate, LoadedAggregate, SnapshotPolicy};
+use sqlx::PgPool;
+#[cfg(feature = "test")]
+use tx_chainable::testing::FromTestPool;
+use tx_chainable::{Execute, GetExecutor, Transaction, Tx, TxCoordinator};
+
+/// Snapshots of aggregates built from [`EventStoreRepository`](crate::EventStoreRepository)
+/// streams, one per stream.
+#[derive(Clone)]
+pub struct SnapshotRepository<E: Execute> {
+    executor: E,
+    coordinator: Option<TxCoordinator>,
+}
+
+impl<E: Execute> Tx for SnapshotRepository<E> {
+    type TxRepository<'tx> = SnapshotRepository<Transaction<'tx>>;
+}
+
+impl<'tx> GetExecutor<'tx> for SnapshotRepository<PgPool> {
+    type Executor = &'tx PgPool;
+    fn get_executor(&'tx self) -> Self::Executor {
+        &self.executor
+    }
+
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        self.coordinator.as_ref()
+    }
+}
+
+impl<'tx> From<SnapshotRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: SnapshotRepository<Transaction<'tx>>) -> Self {
+        repository.executor
+    }
+}
+
+impl<'tx> From<Transaction<'tx>> for SnapshotRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self {
+            executor: tx,
+            coordinator: None,
+        }
+    }
+}
+
+impl SnapshotRepository<PgPool> {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            executor: pool,
+            coordinator: None,
+        }
+    }
+
+    /// Routes `begin` through `coordinator`.
+    pub fn with_coordinator(mut self, coordinator: TxCoordinator) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+}
+
+#[cfg(feature = "test")]
+impl FromTestPool for SnapshotRepository<PgPool> {
+    fn from_test_pool(pool: &PgPool) -> Self {
+        Self::new(pool.clone())
+    }
+}
+
+impl<E: Execute> SnapshotRepository<E> {
+    /// Rebuilds an aggregate from the latest snapshot of its stream and the
+    /// events after it, or from all events if there is no snapshot or it was
+    /// stored with another [`Aggregate::STATE_VERSION`].
+    pub async fn load<A: Aggregate>(
+        &mut self,
+        stream_id: &str,
+    ) -> Result<LoadedAggregate<A>, sqlx::Error> {
+        let snapshot = self
+            .executor
+            .execute(|e| {
+                sqlx::query_as::<_, (i64, serde_json::Value)>(
+                    "SELECT version, state FROM snapshots \
+                     WHERE stream_id = $1 AND state_version = $2",
+                )
+                .bind(stream_id)
+                .bind(A::STATE_VERSION)
+                .fetch_optional(e)
+            })
+            .await?;
+        let (snapshot_version, state) = match snapshot {
+            Some((version, state)) => (
+                version,
+                serde_json::from_value(state).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+            ),
+            None => (0, A::default()),
+        };
+        let events = self
+            .executor
+            .execute(|e| {
+                sqlx::query_as::<_, RecordedEvent>(
+                    "SELECT position, stream_id, version, id, name, payload FROM event_store \
+                     WHERE stream_id = $1 AND version > $2 ORDER BY version",
+                )
+                .bind(stream_id)
+                .bind(snapshot_version)
+                .fetch_all(e)
+            })
+            .await?;
+
+        let mut aggregate = LoadedAggregate {
+            stream_id: stream_id.to_string(),
+            state,
+            version: snapshot_version,
+            snapshot_version,
+        };
+        aggregate.apply(&events);
+        Ok(aggregate)
+    }
+
+    /// Stores `state` as the snapshot of a stream at `version`, unless a
+    /// snapshot at a later version is already stored with the same state
+    /// version, or one with a newer state version.
+    pub async fn save<A: Aggregate>(
+        &mut self,
+        stream_id: &str,
+        version: i64,
+        state: &A,
+    ) -> Result<(), sqlx::Error> {
+        let state = serde_json::to_value(state).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+        self.executor
+            .execute(|e| {
+                sqlx::query(
+                    "INSERT INTO snapshots (stream_id, version, state, state_version) \
+                     VALUES ($1, $2, $3, $4) \
+                     ON CONFLICT (stream_id) DO UPDATE \
+                     SET version = EXCLUDED.version, state = EXCLUDED.state, \
+                         state_version = EXCLUDED.state_version \
+                     WHERE EXCLUDED.state_version >= snapshots.state_version \
+                       AND (snapshots.version < EXCLUDED.version \
+                            OR snapshots.state_version < EXCLUDED.state_version)",
+                )
+                .bind(stream_id)
+                .bind(version)
+                .bind(state)
+                .bind(A::STATE_VERSION)
+                .execute(e)
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Snapshots `aggregate` if `policy` says so, returning whether it did.
+    pub async fn save_if_due<A: Aggregate>(
+        &mut self,
+        aggregate: &mut LoadedAggregate<A>,
+        policy: SnapshotPolicy,
+    ) -> Result<bool, sqlx::Error> {
+        if !policy.is_due(aggregate.snapshot_version, aggregate.version) {
+            return Ok(false);
+        }
+        self.save(&aggregate.stream_id, aggregate.version, &aggregate.state)
+            .await?;
+        aggregate.snapshot_version = aggregate.version;
+        Ok(true)
+    }
+}
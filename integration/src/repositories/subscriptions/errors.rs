@@ -0,0 +1,19 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionError {
+    /// Another owner took over the subscription's lease.
+    LeaseLost { subscription: String },
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LeaseLost { subscription } => {
+                write!(f, "lease on subscription {subscription} was lost")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubscriptionError {}
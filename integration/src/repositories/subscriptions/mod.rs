@@ -0,0 +1,5 @@
+pub mod errors;
+pub mod repository;
+
+pub use errors::*;
+pub use repository::*;
@@ -0,0 +1,171 @@
+use crate::repositories::subscriptions::errors::SubscriptionError;
+use sqlx::PgPool;
+use std::time::Duration;
+#[cfg(feature = "test")]
+use tx_chainable::testing::FromTestPool;
+use tx_chainable::{Execute, GetExecutor, Transaction, Tx, TxCoordinator, TxError};
+use uuid::Uuid;
+
+/// Checkpoints and leases of named subscriptions to the event store.
+///
+/// A lease names the one owner allowed to advance a subscription until it
+/// expires. Owners renew it with [`lock`](Self::lock), which also fences
+/// out an owner whose lease was taken over.
+#[derive(Clone)]
+pub struct SubscriptionRepository<E: Execute> {
+    executor: E,
+    coordinator: Option<TxCoordinator>,
+}
+
+impl<E: Execute> Tx for SubscriptionRepository<E> {
+    type TxRepository<'tx> = SubscriptionRepository<Transaction<'tx>>;
+}
+
+impl<'tx> GetExecutor<'tx> for SubscriptionRepository<PgPool> {
+    type Executor = &'tx PgPool;
+    fn get_executor(&'tx self) -> Self::Executor {
+        &self.executor
+    }
+
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        self.coordinator.as_ref()
+    }
+}
+
+impl<'tx> From<SubscriptionRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: SubscriptionRepository<Transaction<'tx>>) -> Self {
+        repository.executor
+    }
+}
+
+impl<'tx> From<Transaction<'tx>> for SubscriptionRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self {
+            executor: tx,
+            coordinator: None,
+        }
+    }
+}
+
+impl SubscriptionRepository<PgPool> {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            executor: pool,
+            coordinator: None,
+        }
+    }
+
+    /// Routes `begin` through `coordinator`.
+    pub fn with_coordinator(mut self, coordinator: TxCoordinator) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+}
+
+#[cfg(feature = "test")]
+impl FromTestPool for SubscriptionRepository<PgPool> {
+    fn from_test_pool(pool: &PgPool) -> Self {
+        Self::new(pool.clone())
+    }
+}
+
+impl SubscriptionRepository<Transaction<'_>> {
+    /// Renews the lease of `owner` and locks the subscription until the
+    /// transaction ends, returning its checkpoint. Fails with
+    /// [`SubscriptionError::LeaseLost`] if `owner` no longer holds the lease.
+    pub async fn lock(&mut self, name: &str, owner: Uuid, ttl: Duration) -> Result<i64, TxError> {
+        let position = self
+            .executor
+            .execute(|e| {
+                sqlx::query_scalar(
+                    "UPDATE subscriptions SET lease_expires_at = now() + make_interval(secs => $3) \
+                     WHERE name = $1 AND lease_owner = $2 RETURNING position",
+                )
+                .bind(name)
+                .bind(owner)
+                .bind(ttl.as_secs_f64())
+                .fetch_optional(e)
+            })
+            .await?;
+        position.ok_or_else(|| {
+            TxError::Domain(Box::new(SubscriptionError::LeaseLost {
+                subscription: name.to_string(),
+            }))
+        })
+    }
+}
+
+impl<E: Execute> SubscriptionRepository<E> {
+    /// The position of the last event the subscription processed, 0 if none.
+    pub async fn checkpoint(&mut self, name: &str) -> Result<i64, sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query_scalar(
+                    "SELECT COALESCE(MAX(position), 0) FROM subscriptions WHERE name = $1",
+                )
+                .bind(name)
+                .fetch_one(e)
+            })
+            .await
+    }
+
+    pub async fn save_checkpoint(&mut self, name: &str, position: i64) -> Result<(), sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query(
+                    "INSERT INTO subscriptions (name, position) VALUES ($1, $2) \
+                     ON CONFLICT (name) DO UPDATE SET position = EXCLUDED.position",
+                )
+                .bind(name)
+                .bind(position)
+                .execute(e)
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Takes the lease for `owner` if it is free, expired or already held by
+    /// `owner`, returning whether `owner` holds it.
+    pub async fn acquire_lease(
+        &mut self,
+        name: &str,
+        owner: Uuid,
+        ttl: Duration,
+    ) -> Result<bool, sqlx::Error> {
+        let acquired = self
+            .executor
+            .execute(|e| {
+                sqlx::query(
+                    "INSERT INTO subscriptions (name, lease_owner, lease_expires_at) \
+                     VALUES ($1, $2, now() + make_interval(secs => $3)) \
+                     ON CONFLICT (name) DO UPDATE \
+                     SET lease_owner = EXCLUDED.lease_owner, \
+                         lease_expires_at = EXCLUDED.lease_expires_at \
+                     WHERE subscriptions.lease_owner IS NULL \
+                        OR subscriptions.lease_owner = EXCLUDED.lease_owner \
+                        OR subscriptions.lease_expires_at < now()",
+                )
+                .bind(name)
+                .bind(owner)
+                .bind(ttl.as_secs_f64())
+                .execute(e)
+            })
+            .await?;
+        Ok(acquired.rows_affected() == 1)
+    }
+
+    pub async fn release_lease(&mut self, name: &str, owner: Uuid) -> Result<(), sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query(
+                    "UPDATE subscriptions SET lease_owner = NULL, lease_expires_at = NULL \
+                     WHERE name = $1 AND lease_owner = $2",
+                )
+                .bind(name)
+                .bind(owner)
+                .execute(e)
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UsersError {
+    AlreadyExists,
+}
+
+impl fmt::Display for UsersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyExists => f.write_str("user already exists"),
+        }
+    }
+}
+
+impl std::error::Error for UsersError {}
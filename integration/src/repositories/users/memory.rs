@@ -0,0 +1,94 @@
+use crate::repositories::users::models::User;
+use crate::repositories::users::repository::CONSTRAINTS;
+use std::collections::BTreeMap;
+use tx_chainable::{
+    ConstraintRegistry, GetExecutor, MemoryError, MemoryExecute, MemoryStore, Transaction, Tx,
+};
+use uuid::Uuid;
+
+const TABLE: &str = "users";
+
+/// [`UsersRepository`](crate::UsersRepository) backed by a [`MemoryStore`].
+#[derive(Clone)]
+pub struct MemoryUsersRepository<E: MemoryExecute> {
+    executor: E,
+}
+
+impl<E: MemoryExecute> Tx for MemoryUsersRepository<E> {
+    type TxRepository<'tx> = MemoryUsersRepository<Transaction<'tx>>;
+
+    fn constraints() -> &'static ConstraintRegistry {
+        &CONSTRAINTS
+    }
+}
+
+impl<'tx> GetExecutor<'tx> for MemoryUsersRepository<MemoryStore> {
+    type Executor = &'tx MemoryStore;
+    fn get_executor(&'tx self) -> Self::Executor {
+        &self.executor
+    }
+}
+
+impl<'tx> From<MemoryUsersRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: MemoryUsersRepository<Transaction<'tx>>) -> Self {
+        repository.executor
+    }
+}
+
+impl<'tx> From<Transaction<'tx>> for MemoryUsersRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self { executor: tx }
+    }
+}
+
+impl MemoryUsersRepository<MemoryStore> {
+    pub fn new(store: MemoryStore) -> Self {
+        Self { executor: store }
+    }
+}
+
+impl<E: MemoryExecute> MemoryUsersRepository<E> {
+    pub async fn get_users(&mut self, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+        self.executor.read(TABLE, |users: &BTreeMap<Uuid, User>| {
+            let mut users: Vec<_> = users.values().cloned().collect();
+            users.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+            users.truncate(limit.max(0) as usize);
+            users
+        })
+    }
+
+    pub async fn create_user(&mut self, id: Uuid, name: String) -> Result<User, sqlx::Error> {
+        self.executor.write(TABLE, |users| {
+            if users.contains_key(&id) {
+                return Err(MemoryError::unique_violation("users_pkey").into());
+            }
+            let user = User { id, name };
+            users.insert(id, user.clone());
+            Ok(user)
+        })?
+    }
+
+    pub async fn rename_user(
+        &mut self,
+        id: Uuid,
+        name: String,
+    ) -> Result<Option<User>, sqlx::Error> {
+        self.executor
+            .write(TABLE, |users: &mut BTreeMap<Uuid, User>| {
+                users.get_mut(&id).map(|user| {
+                    user.name = name;
+                    user.clone()
+                })
+            })
+    }
+
+    /// Returns the number of users deleted.
+    pub async fn delete_users(&mut self) -> Result<u64, sqlx::Error> {
+        self.executor
+            .write(TABLE, |users: &mut BTreeMap<Uuid, User>| {
+                let deleted = users.len() as u64;
+                users.clear();
+                deleted
+            })
+    }
+}
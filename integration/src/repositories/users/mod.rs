@@ -1,5 +1,9 @@
+pub mod errors;
+pub mod memory;
 pub mod models;
 pub mod repository;
 
+pub use errors::*;
+pub use memory::*;
 pub use models::*;
 pub use repository::*;
@@ -1,15 +1,27 @@
+use crate::repositories::users::errors::UsersError;
 use crate::repositories::users::models::User;
-use sqlx::{PgPool, PgTransaction};
-use tx_chainable::{Execute, GetExecutor, Tx};
+use sqlx::PgPool;
+use std::sync::LazyLock;
+#[cfg(feature = "test")]
+use tx_chainable::testing::FromTestPool;
+use tx_chainable::{ConstraintRegistry, Execute, GetExecutor, Transaction, Tx, TxCoordinator};
 use uuid::Uuid;
 
+pub(crate) static CONSTRAINTS: LazyLock<ConstraintRegistry> =
+    LazyLock::new(|| ConstraintRegistry::new().map("users_pkey", |_| UsersError::AlreadyExists));
+
 #[derive(Clone)]
 pub struct UsersRepository<E: Execute> {
     executor: E,
+    coordinator: Option<TxCoordinator>,
 }
 
 impl<E: Execute> Tx for UsersRepository<E> {
-    type TxRepository<'tx> = UsersRepository<PgTransaction<'tx>>;
+    type TxRepository<'tx> = UsersRepository<Transaction<'tx>>;
+
+    fn constraints() -> &'static ConstraintRegistry {
+        &CONSTRAINTS
+    }
 }
 
 impl<'tx> GetExecutor<'tx> for UsersRepository<PgPool> {
@@ -17,23 +29,46 @@ impl<'tx> GetExecutor<'tx> for UsersRepository<PgPool> {
     fn get_executor(&'tx self) -> Self::Executor {
         &self.executor
     }
+
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        self.coordinator.as_ref()
+    }
 }
 
-impl<'tx> Into<PgTransaction<'tx>> for UsersRepository<PgTransaction<'tx>> {
-    fn into(self) -> PgTransaction<'tx> {
-        self.executor
+impl<'tx> From<UsersRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: UsersRepository<Transaction<'tx>>) -> Self {
+        repository.executor
     }
 }
 
-impl<'tx> From<PgTransaction<'tx>> for UsersRepository<PgTransaction<'tx>> {
-    fn from(tx: PgTransaction<'tx>) -> Self {
-        Self { executor: tx }
+impl<'tx> From<Transaction<'tx>> for UsersRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self {
+            executor: tx,
+            coordinator: None,
+        }
     }
 }
 
 impl UsersRepository<PgPool> {
     pub fn new(pool: PgPool) -> Self {
-        Self { executor: pool }
+        Self {
+            executor: pool,
+            coordinator: None,
+        }
+    }
+
+    /// Routes `begin` through `coordinator`.
+    pub fn with_coordinator(mut self, coordinator: TxCoordinator) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+}
+
+#[cfg(feature = "test")]
+impl FromTestPool for UsersRepository<PgPool> {
+    fn from_test_pool(pool: &PgPool) -> Self {
+        Self::new(pool.clone())
     }
 }
 
@@ -54,10 +89,50 @@ impl<E: Execute> UsersRepository<E> {
                 sqlx::query_as::<_, User>(
                     "INSERT INTO users (id, name) VALUES ($1, $2) RETURNING id, name",
                 )
-                .bind(&id)
-                .bind(&name)
+                .bind(id)
+                .bind(name)
                 .fetch_one(e)
             })
             .await
     }
+
+    /// Returns `None` if there is no user with `id`.
+    pub async fn rename_user(
+        &mut self,
+        id: Uuid,
+        name: String,
+    ) -> Result<Option<User>, sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query_as::<_, User>(
+                    "UPDATE users SET name = $2 WHERE id = $1 RETURNING id, name",
+                )
+                .bind(id)
+                .bind(name)
+                .fetch_optional(e)
+            })
+            .await
+    }
+
+    /// Returns whether there was a user with `id`.
+    pub async fn delete_user(&mut self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = self
+            .executor
+            .execute(|e| {
+                sqlx::query("DELETE FROM users WHERE id = $1")
+                    .bind(id)
+                    .execute(e)
+            })
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the number of users deleted.
+    pub async fn delete_users(&mut self) -> Result<u64, sqlx::Error> {
+        let result = self
+            .executor
+            .execute(|e| sqlx::query("DELETE FROM users").execute(e))
+            .await?;
+        Ok(result.rows_affected())
+    }
 }
@@ -0,0 +1,183 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tx_chainable::{Begin, CircuitBreaker, CircuitState, DbErrorKind, TxCoordinator, TxError};
+use tx_chainable_integration::UsersRepository;
+
+type Transitions = Arc<Mutex<Vec<(CircuitState, CircuitState)>>>;
+
+fn coordinator(transitions: &Transitions) -> TxCoordinator {
+    let transitions = transitions.clone();
+    TxCoordinator::new().circuit_breaker(
+        CircuitBreaker::new(2)
+            .open_for(Duration::from_millis(200))
+            .on_state_change(move |from, to| transitions.lock().unwrap().push((from, to))),
+    )
+}
+
+/// A pool whose server hangs up on every connection, as while Postgres is
+/// restarting.
+async fn unreachable_pool() -> PgPool {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind a local port");
+    let port = listener.local_addr().expect("bound address").port();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            drop(socket);
+        }
+    });
+    PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy(&format!("postgres://postgres@127.0.0.1:{port}/postgres"))
+        .expect("valid connection string")
+}
+
+/// A pool whose server refuses every connection, as once the host of a
+/// failed-over primary is gone. sqlx retries refused connections until the
+/// acquire timeout runs out.
+async fn refusing_pool() -> PgPool {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind a local port")
+        .local_addr()
+        .expect("bound address")
+        .port();
+    PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(100))
+        .connect_lazy(&format!("postgres://postgres@127.0.0.1:{port}/postgres"))
+        .expect("valid connection string")
+}
+
+async fn begin_empty(users_repo: &UsersRepository<PgPool>) -> Result<(), TxError> {
+    users_repo
+        .begin(|users| Box::pin(async move { Ok(users) }))
+        .await
+}
+
+fn is_connection_failure(error: &TxError) -> bool {
+    error.kind() == Some(DbErrorKind::ConnectionLost)
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_breaker_opens_and_recovers(pool: PgPool) -> anyhow::Result<()> {
+    let transitions = Transitions::default();
+    let coordinator = coordinator(&transitions);
+    let failing_repo =
+        UsersRepository::new(unreachable_pool().await).with_coordinator(coordinator.clone());
+    let users_repo = UsersRepository::new(pool).with_coordinator(coordinator.clone());
+
+    for _ in 0..2 {
+        let error = begin_empty(&failing_repo).await.expect_err("unreachable");
+        assert!(is_connection_failure(&error), "unexpected error {error:?}");
+    }
+    assert_eq!(Some(CircuitState::Open), coordinator.circuit_state());
+
+    // While open, even a healthy pool fails fast
+    assert!(matches!(
+        begin_empty(&users_repo).await,
+        Err(TxError::CircuitOpen)
+    ));
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    begin_empty(&users_repo).await?;
+    assert_eq!(Some(CircuitState::Closed), coordinator.circuit_state());
+    assert_eq!(
+        vec![
+            (CircuitState::Closed, CircuitState::Open),
+            (CircuitState::Open, CircuitState::HalfOpen),
+            (CircuitState::HalfOpen, CircuitState::Closed),
+        ],
+        *transitions.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_failed_probe_reopens_breaker(pool: PgPool) -> anyhow::Result<()> {
+    let transitions = Transitions::default();
+    let coordinator = coordinator(&transitions);
+    let failing_repo =
+        UsersRepository::new(unreachable_pool().await).with_coordinator(coordinator.clone());
+    let users_repo = UsersRepository::new(pool).with_coordinator(coordinator.clone());
+
+    // A failure followed by a success does not count as consecutive
+    assert!(begin_empty(&failing_repo).await.is_err());
+    begin_empty(&users_repo).await?;
+    assert!(begin_empty(&failing_repo).await.is_err());
+    assert_eq!(Some(CircuitState::Closed), coordinator.circuit_state());
+
+    assert!(begin_empty(&failing_repo).await.is_err());
+    assert_eq!(Some(CircuitState::Open), coordinator.circuit_state());
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    let error = begin_empty(&failing_repo).await.expect_err("unreachable");
+    assert!(is_connection_failure(&error), "unexpected error {error:?}");
+    assert_eq!(Some(CircuitState::Open), coordinator.circuit_state());
+    assert!(matches!(
+        begin_empty(&users_repo).await,
+        Err(TxError::CircuitOpen)
+    ));
+    assert_eq!(
+        vec![
+            (CircuitState::Closed, CircuitState::Open),
+            (CircuitState::Open, CircuitState::HalfOpen),
+            (CircuitState::HalfOpen, CircuitState::Open),
+        ],
+        *transitions.lock().unwrap()
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_refused_connections_open_breaker(pool: PgPool) -> anyhow::Result<()> {
+    let transitions = Transitions::default();
+    let coordinator = coordinator(&transitions);
+    let failing_repo =
+        UsersRepository::new(refusing_pool().await).with_coordinator(coordinator.clone());
+    let users_repo = UsersRepository::new(pool).with_coordinator(coordinator.clone());
+
+    for _ in 0..2 {
+        assert!(matches!(
+            begin_empty(&failing_repo).await,
+            Err(TxError::Database(sqlx::Error::PoolTimedOut))
+        ));
+    }
+    assert_eq!(Some(CircuitState::Open), coordinator.circuit_state());
+    assert!(matches!(
+        begin_empty(&failing_repo).await,
+        Err(TxError::CircuitOpen)
+    ));
+    assert!(matches!(
+        begin_empty(&users_repo).await,
+        Err(TxError::CircuitOpen)
+    ));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_exhausted_pool_keeps_breaker_closed(pool: PgPool) -> anyhow::Result<()> {
+    let transitions = Transitions::default();
+    let coordinator = coordinator(&transitions);
+    let exhausted = PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(Duration::from_millis(50))
+        .connect_with(pool.connect_options().as_ref().clone())
+        .await?;
+    let users_repo = UsersRepository::new(exhausted.clone()).with_coordinator(coordinator.clone());
+
+    let held = exhausted.acquire().await?;
+    for _ in 0..3 {
+        assert!(matches!(
+            begin_empty(&users_repo).await,
+            Err(TxError::Database(sqlx::Error::PoolTimedOut))
+        ));
+    }
+    assert_eq!(Some(CircuitState::Closed), coordinator.circuit_state());
+    drop(held);
+    begin_empty(&users_repo).await?;
+    assert!(transitions.lock().unwrap().is_empty());
+    Ok(())
+}
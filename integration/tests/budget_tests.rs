@@ -0,0 +1,137 @@
+use std::time::Duration;
+use tx_chainable::{
+    Begin, BudgetAction, BudgetExceeded, BudgetLimit, Chainable, TxBudget, TxError, TxOptions,
+};
+use tx_chainable_integration::{EventsRepository, UsersRepository};
+use uuid::Uuid;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_statement_budget_names_repository(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let options = TxOptions::new().budget(TxBudget::new().max_statements(2));
+    let result = events_repo
+        .begin_with(options, |mut events| {
+            Box::pin(async move {
+                events
+                    .create_event(Uuid::new_v4(), "batch".to_string(), serde_json::json!({}))
+                    .await?;
+                let events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            // N+1: one statement per user
+                            for i in 0..3 {
+                                users
+                                    .create_user(Uuid::new_v4(), format!("User {i}"))
+                                    .await?;
+                            }
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await;
+
+    match result {
+        Err(TxError::BudgetExceeded(exceeded)) => assert_eq!(
+            BudgetExceeded {
+                repository: "UsersRepository",
+                limit: BudgetLimit::Statements(2),
+            },
+            exceeded
+        ),
+        other => panic!("expected a budget error, got {other:?}"),
+    }
+
+    let users = UsersRepository::new(pool.clone()).get_users(10).await?;
+    assert!(
+        users.is_empty(),
+        "Users table should be empty after rollback"
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_row_budget(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let mut users = UsersRepository::new(pool.clone());
+    for i in 0..3 {
+        users
+            .create_user(Uuid::new_v4(), format!("User {i}"))
+            .await?;
+    }
+
+    let users_repo = UsersRepository::new(pool.clone());
+    let options = TxOptions::new().budget(TxBudget::new().max_rows(2));
+    let result = users_repo
+        .begin_with(options, |mut users| {
+            Box::pin(async move {
+                users.get_users(10).await?;
+                Ok(users)
+            })
+        })
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(TxError::BudgetExceeded(BudgetExceeded {
+            limit: BudgetLimit::Rows(2),
+            ..
+        }))
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_duration_budget(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let options = TxOptions::new().budget(TxBudget::new().max_duration(Duration::from_millis(10)));
+    let result = users_repo
+        .begin_with(options, |mut users| {
+            Box::pin(async move {
+                users.get_users(10).await?;
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                users.get_users(10).await?;
+                Ok(users)
+            })
+        })
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(TxError::BudgetExceeded(BudgetExceeded {
+            limit: BudgetLimit::Duration(_),
+            ..
+        }))
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_warn_budget_commits(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let budget = TxBudget::new().max_statements(1).action(BudgetAction::Warn);
+    users_repo
+        .begin_with(TxOptions::new().budget(budget), |mut users| {
+            Box::pin(async move {
+                for i in 0..3 {
+                    users
+                        .create_user(Uuid::new_v4(), format!("User {i}"))
+                        .await?;
+                }
+                Ok(users)
+            })
+        })
+        .await?;
+
+    let users = UsersRepository::new(pool.clone()).get_users(10).await?;
+    assert_eq!(3, users.len());
+
+    Ok(())
+}
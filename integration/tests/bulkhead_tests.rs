@@ -0,0 +1,144 @@
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use sqlx::PgPool;
+use std::time::Duration;
+use tx_chainable::{Begin, Bulkhead, TxCoordinator, TxError, TxOptions};
+use tx_chainable_integration::UsersRepository;
+use uuid::Uuid;
+
+mod other {
+    use tx_chainable::{Transaction, Tx};
+
+    /// Shares its name with the integration crate's repository.
+    pub struct UsersRepository;
+
+    impl Tx for UsersRepository {
+        type TxRepository<'tx> = Transaction<'tx>;
+    }
+}
+
+/// Begins a transaction that inserts a user and then holds the connection for `hold`.
+async fn hold_transaction(
+    users_repo: &UsersRepository<PgPool>,
+    options: TxOptions,
+    hold: Duration,
+) -> Result<(), TxError> {
+    users_repo
+        .begin_with(options, |mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(Uuid::new_v4(), "Bulkhead".to_string())
+                    .await?;
+                tokio::time::sleep(hold).await;
+                Ok(users)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_bulkhead_rejects_after_queue_timeout(pool: PgPool) -> anyhow::Result<()> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let coordinator = TxCoordinator::new()
+        .bulkhead(Bulkhead::for_name("import", 1).queue_timeout(Duration::from_millis(50)));
+    let users_repo = UsersRepository::new(pool.clone()).with_coordinator(coordinator);
+
+    let (holder, rejected, other) = tokio::join!(
+        hold_transaction(
+            &users_repo,
+            TxOptions::new().name("import"),
+            Duration::from_millis(300)
+        ),
+        async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            hold_transaction(&users_repo, TxOptions::new().name("import"), Duration::ZERO).await
+        },
+        async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            hold_transaction(&users_repo, TxOptions::new().name("signup"), Duration::ZERO).await
+        },
+    );
+
+    holder?;
+    other?;
+    match rejected {
+        Err(TxError::Overloaded { bulkhead }) => assert_eq!("import", bulkhead),
+        result => panic!("expected the import bulkhead to reject, got {result:?}"),
+    }
+
+    let recorded = snapshotter.snapshot().into_vec();
+    let metric = |name: &str| {
+        recorded
+            .iter()
+            .find(|(key, _, _, _)| {
+                key.key().name() == name
+                    && key
+                        .key()
+                        .labels()
+                        .any(|label| label.key() == "bulkhead" && label.value() == "import")
+            })
+            .map(|(_, _, _, value)| value)
+    };
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        metric("tx_chainable_bulkhead_rejections_total")
+    );
+    assert_eq!(
+        Some(&DebugValue::Gauge(0.0.into())),
+        metric("tx_chainable_bulkhead_queue_depth")
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_bulkhead_queues_per_repository(pool: PgPool) -> anyhow::Result<()> {
+    let coordinator = TxCoordinator::new().bulkhead(
+        Bulkhead::for_repository::<UsersRepository<PgPool>>(1)
+            .queue_timeout(Duration::from_secs(5)),
+    );
+    let users_repo = UsersRepository::new(pool.clone()).with_coordinator(coordinator.clone());
+
+    let (holder, queued, depth) = tokio::join!(
+        hold_transaction(&users_repo, TxOptions::new(), Duration::from_millis(200)),
+        async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            hold_transaction(&users_repo, TxOptions::new(), Duration::ZERO).await
+        },
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            coordinator.queue_depth("UsersRepository")
+        },
+    );
+
+    holder?;
+    queued?;
+    assert_eq!(Some(1), depth);
+    assert_eq!(Some(0), coordinator.queue_depth("UsersRepository"));
+
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(2, users);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_bulkheads_are_shared_and_keyed_by_type_path(pool: PgPool) -> anyhow::Result<()> {
+    let coordinator = TxCoordinator::new();
+    let users_repo = UsersRepository::new(pool).with_coordinator(coordinator.clone());
+
+    let _ = coordinator
+        .clone()
+        .bulkhead(Bulkhead::for_repository::<other::UsersRepository>(0));
+    hold_transaction(&users_repo, TxOptions::new(), Duration::ZERO).await?;
+
+    // Added after the repository got its clone of the coordinator
+    let _ = coordinator.bulkhead(Bulkhead::for_repository::<UsersRepository<PgPool>>(0));
+    match hold_transaction(&users_repo, TxOptions::new(), Duration::ZERO).await {
+        Err(TxError::Overloaded { bulkhead }) => assert_eq!("UsersRepository", bulkhead),
+        result => panic!("expected the UsersRepository bulkhead to reject, got {result:?}"),
+    }
+    Ok(())
+}
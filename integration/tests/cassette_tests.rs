@@ -0,0 +1,90 @@
+use sqlx::PgPool;
+use tx_chainable::cassette::{Cassette, CassetteError, Player, Recorder};
+use tx_chainable::{Begin, TxError};
+use tx_chainable_integration::{User, UsersRepository};
+use uuid::Uuid;
+
+const USER_ID: Uuid = Uuid::from_u128(1);
+
+async fn register(pool: &PgPool, name: &str) -> Result<Vec<User>, TxError> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let name = name.to_string();
+    users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users.create_user(USER_ID, name).await?;
+                Ok(users)
+            })
+        })
+        .await?;
+    Ok(UsersRepository::new(pool.clone()).get_users(10).await?)
+}
+
+async fn record(pool: &PgPool) -> anyhow::Result<(Vec<User>, Cassette)> {
+    let recorder = Recorder::start((*pool.connect_options()).clone()).await?;
+    let users = register(recorder.pool(), "Recorded").await?;
+    Ok((users, recorder.finish().await))
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_replay_without_database(pool: PgPool) -> anyhow::Result<()> {
+    let (recorded, cassette) = record(&pool).await?;
+    let path = std::env::temp_dir().join(format!("cassette-{}.json", Uuid::new_v4()));
+    cassette.save(&path)?;
+    let cassette = Cassette::load(&path)?;
+    std::fs::remove_file(&path)?;
+    pool.close().await;
+
+    let player = Player::start(cassette).await?;
+    let replayed = register(player.pool(), "Recorded").await?;
+    player.finish().await?;
+
+    assert_eq!(recorded, replayed);
+    assert_eq!(
+        vec!["Recorded"],
+        replayed.iter().map(|u| u.name.as_str()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_replay_fails_when_parameters_diverge(pool: PgPool) -> anyhow::Result<()> {
+    let (_, cassette) = record(&pool).await?;
+
+    let player = Player::start(cassette).await?;
+    let error = register(player.pool(), "Diverged")
+        .await
+        .expect_err("the name differs from the recording");
+    assert!(matches!(error, TxError::Database(sqlx::Error::Database(_))));
+
+    match player.finish().await {
+        Err(CassetteError::Mismatch {
+            expected, actual, ..
+        }) => {
+            assert!(expected.contains("\"Recorded\""), "{expected}");
+            assert!(actual.contains("\"Diverged\""), "{actual}");
+        }
+        result => panic!("expected a mismatch, got {result:?}"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_invalid_hex_fails_to_load() -> anyhow::Result<()> {
+    for body in ["0é0", "0", "+1", "zz"] {
+        let path = std::env::temp_dir().join(format!("cassette-{}.json", Uuid::new_v4()));
+        let document = serde_json::json!({
+            "interactions": [{ "request": [{ "tag": "Q", "body": body }], "response": [] }]
+        });
+        std::fs::write(&path, document.to_string())?;
+        let loaded = Cassette::load(&path);
+        std::fs::remove_file(&path)?;
+        let error = loaded.expect_err("not hex");
+        assert_eq!(
+            std::io::ErrorKind::InvalidData,
+            error.kind(),
+            "{body}: {error}"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+use tx_chainable::{Begin, ShutdownReport, TxCoordinator, TxError};
+use tx_chainable_integration::UsersRepository;
+use uuid::Uuid;
+
+async fn wait_for_in_flight(coordinator: &TxCoordinator) {
+    for _ in 0..100 {
+        if coordinator.in_flight() > 0 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("transaction never started");
+}
+
+async fn count_users(pool: &sqlx::PgPool) -> sqlx::Result<i64> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_shutdown_drains_in_flight(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let coordinator = TxCoordinator::new();
+    let users_repo = UsersRepository::new(pool.clone()).with_coordinator(coordinator.clone());
+
+    let in_flight = tokio::spawn({
+        let users_repo = users_repo.clone();
+        async move {
+            users_repo
+                .begin(|mut users| {
+                    Box::pin(async move {
+                        tokio::time::sleep(Duration::from_millis(200)).await;
+                        users
+                            .create_user(Uuid::new_v4(), "Draining".to_string())
+                            .await?;
+                        Ok(users)
+                    })
+                })
+                .await
+        }
+    });
+    wait_for_in_flight(&coordinator).await;
+
+    let shutdown = tokio::spawn({
+        let coordinator = coordinator.clone();
+        async move { coordinator.shutdown(Duration::from_secs(5)).await }
+    });
+    while !coordinator.is_shutting_down() {
+        tokio::task::yield_now().await;
+    }
+
+    let rejected = users_repo
+        .begin(|users| Box::pin(async move { Ok(users) }))
+        .await;
+    assert!(matches!(rejected, Err(TxError::ShuttingDown)));
+
+    in_flight.await??;
+    assert_eq!(
+        ShutdownReport {
+            drained: 1,
+            aborted: 0
+        },
+        shutdown.await?
+    );
+    assert_eq!(0, coordinator.in_flight());
+    assert_eq!(1, count_users(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_shutdown_rolls_back_at_deadline(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let coordinator = TxCoordinator::new();
+    let users_repo = UsersRepository::new(pool.clone()).with_coordinator(coordinator.clone());
+
+    let in_flight = tokio::spawn(async move {
+        users_repo
+            .begin(|mut users| {
+                Box::pin(async move {
+                    users
+                        .create_user(Uuid::new_v4(), "Stuck".to_string())
+                        .await?;
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(users)
+                })
+            })
+            .await
+    });
+    wait_for_in_flight(&coordinator).await;
+
+    let report = coordinator.shutdown(Duration::from_millis(100)).await;
+    assert_eq!(
+        ShutdownReport {
+            drained: 0,
+            aborted: 1
+        },
+        report
+    );
+    // Rolled back before shutdown returned, not left to the pool
+    let idle_in_transaction: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pg_stat_activity \
+         WHERE datname = current_database() AND state = 'idle in transaction'",
+    )
+    .fetch_one(&pool)
+    .await?;
+    assert_eq!(0, idle_in_transaction);
+    assert!(matches!(in_flight.await?, Err(TxError::ShuttingDown)));
+    assert_eq!(0, count_users(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_second_shutdown_reports_only_its_own_aborts(
+    pool: sqlx::PgPool,
+) -> anyhow::Result<()> {
+    let coordinator = TxCoordinator::new();
+    let users_repo = UsersRepository::new(pool).with_coordinator(coordinator.clone());
+
+    let in_flight = tokio::spawn(async move {
+        users_repo
+            .begin(|users| {
+                Box::pin(async move {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(users)
+                })
+            })
+            .await
+    });
+    wait_for_in_flight(&coordinator).await;
+    let first = coordinator.shutdown(Duration::from_millis(100)).await;
+    assert_eq!(1, first.aborted);
+    assert!(matches!(in_flight.await?, Err(TxError::ShuttingDown)));
+
+    assert_eq!(
+        ShutdownReport {
+            drained: 0,
+            aborted: 0
+        },
+        coordinator.shutdown(Duration::from_millis(100)).await
+    );
+    Ok(())
+}
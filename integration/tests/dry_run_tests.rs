@@ -0,0 +1,92 @@
+use sqlx::PgPool;
+use tx_chainable::{Begin, Chainable, MemoryStore};
+use tx_chainable_integration::{EventsRepository, MemoryUsersRepository, UsersRepository};
+use uuid::Uuid;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_dry_run_returns_value_and_rolls_back(pool: PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    let ((created, dry_run), report) = events_repo
+        .begin_dry_run(|events| {
+            Box::pin(async move {
+                let mut events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            users.create_user(user_id, "Preview".to_string()).await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                let event = events
+                    .create_event(
+                        Uuid::new_v4(),
+                        "previewed".to_string(),
+                        serde_json::json!({}),
+                    )
+                    .await?;
+                let dry_run = events.is_dry_run();
+                Ok((events, (event.name, dry_run)))
+            })
+        })
+        .await?;
+
+    assert_eq!("previewed", created);
+    assert!(dry_run);
+    assert_eq!(1, report.chain_hops);
+    assert_eq!(
+        vec!["INSERT", "INSERT"],
+        report
+            .statements
+            .iter()
+            .map(|s| s.sql.split_whitespace().next().unwrap())
+            .collect::<Vec<_>>()
+    );
+
+    let users = UsersRepository::new(pool.clone()).get_users(10).await?;
+    assert!(users.is_empty());
+    let events = EventsRepository::new(pool).get_events(10).await?;
+    assert!(events.is_empty());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_begin_is_not_a_dry_run(pool: PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool);
+    events_repo
+        .begin(|events| {
+            Box::pin(async move {
+                assert!(!events.is_dry_run());
+                Ok(events)
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_memory_dry_run_discards_staged_writes() -> anyhow::Result<()> {
+    let store = MemoryStore::new();
+    let users_repo = MemoryUsersRepository::new(store.clone());
+
+    let (staged, _) = users_repo
+        .begin_dry_run(|mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(Uuid::new_v4(), "Preview".to_string())
+                    .await?;
+                let staged = users.get_users(10).await?.len();
+                Ok((users, staged))
+            })
+        })
+        .await?;
+
+    assert_eq!(1, staged);
+    assert!(MemoryUsersRepository::new(store)
+        .get_users(10)
+        .await?
+        .is_empty());
+    Ok(())
+}
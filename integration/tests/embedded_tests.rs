@@ -0,0 +1,31 @@
+use sqlx::{Connection, PgConnection, PgPool};
+use tx_chainable::Begin;
+use tx_chainable_integration::{embedded_database, UsersRepository};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_embedded_postgres_runs_migrations_and_stops() -> anyhow::Result<()> {
+    let server = embedded_database().await?;
+
+    let pool = PgPool::connect(&server.url()).await?;
+    let users_repo = UsersRepository::new(pool.clone());
+    users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(Uuid::new_v4(), "Embedded".to_string())
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await?;
+    assert_eq!(1, users_repo.clone().get_users(10).await?.len());
+    pool.close().await;
+
+    let port = server.port();
+    drop(server);
+    let stopped =
+        PgConnection::connect(&format!("postgres://postgres@localhost:{port}/postgres")).await;
+    assert!(stopped.is_err());
+    Ok(())
+}
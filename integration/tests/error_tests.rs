@@ -0,0 +1,113 @@
+use tx_chainable::{Begin, Chainable, DbErrorKind, TxError};
+use tx_chainable_integration::{EventsRepository, UsersError, UsersRepository};
+use uuid::Uuid;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_unique_violation_is_classified(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let event_id = Uuid::new_v4();
+
+    // events_pkey has no mapping, so the classified database error surfaces
+    let result = events_repo
+        .begin(|mut events| {
+            Box::pin(async move {
+                for _ in 0..2 {
+                    events
+                        .create_event(event_id, "duplicate".to_string(), serde_json::json!({}))
+                        .await?;
+                }
+                Ok(events)
+            })
+        })
+        .await;
+
+    let error = result.expect_err("duplicate event id should fail");
+    assert!(matches!(error, TxError::Database(_)));
+    assert_eq!(
+        Some(DbErrorKind::UniqueViolation {
+            constraint: Some("events_pkey".to_string())
+        }),
+        error.kind()
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_constraint_mapped_to_domain_error_in_begin(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    UsersRepository::new(pool.clone())
+        .create_user(user_id, "Existing User".to_string())
+        .await?;
+
+    let result = users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(user_id, "Duplicate User".to_string())
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await;
+
+    let error = result.expect_err("duplicate user id should fail");
+    assert_eq!(
+        Some(&UsersError::AlreadyExists),
+        error.domain::<UsersError>()
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_constraint_mapped_to_domain_error_in_chain(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    // The users registry applies even though the transaction began on events
+    let result = events_repo
+        .begin(|events| {
+            Box::pin(async move {
+                let events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            users.create_user(user_id, "First".to_string()).await?;
+                            users.create_user(user_id, "Second".to_string()).await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await;
+
+    let error = result.expect_err("duplicate user id should fail");
+    assert_eq!(
+        Some(&UsersError::AlreadyExists),
+        error.domain::<UsersError>()
+    );
+    assert_eq!(None, error.kind());
+
+    // Nothing was committed
+    let users = UsersRepository::new(pool.clone()).get_users(10).await?;
+    assert!(
+        users.is_empty(),
+        "Users table should be empty after rollback"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_non_database_errors_are_not_classified() {
+    assert_eq!(None, DbErrorKind::classify(&sqlx::Error::RowNotFound));
+    assert_eq!(
+        Some(DbErrorKind::ConnectionLost),
+        DbErrorKind::classify(&sqlx::Error::PoolClosed)
+    );
+}
@@ -0,0 +1,144 @@
+use sqlx::PgPool;
+use tx_chainable::{Begin, EscapeAction, EscapeDetector, TxError, TxOptions};
+use tx_chainable_integration::UsersRepository;
+use uuid::Uuid;
+
+async fn user_count(pool: &PgPool) -> sqlx::Result<i64> {
+    sqlx::query_scalar("SELECT count(*) FROM users")
+        .fetch_one(pool)
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_pool_write_inside_begin_fails_it(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let escaping_pool = pool.clone();
+
+    let result = users_repo
+        .begin_with(
+            TxOptions::new().on_escape(EscapeAction::Fail),
+            |mut users| {
+                Box::pin(async move {
+                    users
+                        .create_user(Uuid::new_v4(), "Inside".to_string())
+                        .await?;
+                    // The bug: a repository on the pool, not the transaction
+                    UsersRepository::new(escaping_pool)
+                        .create_user(Uuid::new_v4(), "Escaped".to_string())
+                        .await?;
+                    Ok(users)
+                })
+            },
+        )
+        .await;
+
+    assert!(
+        matches!(
+            result,
+            Err(TxError::Escaped {
+                operation: "PgPool::execute"
+            })
+        ),
+        "{result:?}"
+    );
+    // The transaction was rolled back, the escaped write was not
+    let users = UsersRepository::new(pool).get_users(10).await?;
+    assert_eq!(
+        vec!["Escaped"],
+        users.iter().map(|u| u.name.as_str()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_nested_begin_fails_outer_transaction(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let nested_repo = UsersRepository::new(pool);
+
+    let result = users_repo
+        .begin_with(TxOptions::new().on_escape(EscapeAction::Fail), |users| {
+            Box::pin(async move {
+                nested_repo
+                    .begin(|users| Box::pin(async move { Ok(users) }))
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await;
+
+    assert!(
+        matches!(result, Err(TxError::Escaped { operation: "begin" })),
+        "{result:?}"
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_transaction_is_marked_only_while_closure_runs(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    assert!(!EscapeDetector::in_transaction());
+
+    users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                assert!(EscapeDetector::in_transaction());
+                users
+                    .create_user(Uuid::new_v4(), "Inside".to_string())
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await?;
+
+    assert!(!EscapeDetector::in_transaction());
+    // The pool is usable again once the transaction has ended
+    let users = UsersRepository::new(pool).get_users(10).await?;
+    assert_eq!(1, users.len());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_escaped_write_warns_by_default(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let escaping_pool = pool.clone();
+
+    // A failing transaction elsewhere does not change this one's action
+    let (warned, failed) = tokio::join!(
+        tokio::spawn(async move {
+            users_repo
+                .begin(|mut users| {
+                    Box::pin(async move {
+                        UsersRepository::new(escaping_pool)
+                            .create_user(Uuid::new_v4(), "Escaped".to_string())
+                            .await?;
+                        users
+                            .create_user(Uuid::new_v4(), "Inside".to_string())
+                            .await?;
+                        Ok(users)
+                    })
+                })
+                .await
+        }),
+        tokio::spawn({
+            let users_repo = UsersRepository::new(pool.clone());
+            let nested_repo = UsersRepository::new(pool.clone());
+            async move {
+                users_repo
+                    .begin_with(TxOptions::new().on_escape(EscapeAction::Fail), |users| {
+                        Box::pin(async move {
+                            nested_repo
+                                .begin(|users| Box::pin(async move { Ok(users) }))
+                                .await?;
+                            Ok(users)
+                        })
+                    })
+                    .await
+            }
+        }),
+    );
+
+    warned??;
+    assert!(matches!(failed?, Err(TxError::Escaped { .. })));
+    assert_eq!(2, user_count(&pool).await?);
+    Ok(())
+}
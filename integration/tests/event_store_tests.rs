@@ -0,0 +1,219 @@
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tx_chainable::{Begin, Chainable, IsolationLevel, TxError, TxOptions};
+use tx_chainable_integration::{
+    Event, EventStoreError, EventStoreRepository, ExpectedVersion, UsersRepository,
+};
+use uuid::Uuid;
+
+fn event(name: &str) -> Event {
+    Event {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        payload: serde_json::json!({ "name": name }),
+    }
+}
+
+async fn append(
+    store: &EventStoreRepository<PgPool>,
+    stream_id: &'static str,
+    expected: ExpectedVersion,
+    events: Vec<Event>,
+) -> Result<(), TxError> {
+    store
+        .begin(|mut store| {
+            Box::pin(async move {
+                store.append(stream_id, expected, events).await?;
+                Ok(store)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_append_assigns_versions_and_positions(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool);
+
+    append(
+        &store,
+        "order-1",
+        ExpectedVersion::NoStream,
+        vec![event("created"), event("paid")],
+    )
+    .await?;
+    append(
+        &store,
+        "order-2",
+        ExpectedVersion::Any,
+        vec![event("created")],
+    )
+    .await?;
+    append(
+        &store,
+        "order-1",
+        ExpectedVersion::Exact(2),
+        vec![event("shipped")],
+    )
+    .await?;
+
+    let mut reader = store.clone();
+    assert_eq!(3, reader.stream_version("order-1").await?);
+    assert_eq!(0, reader.stream_version("order-3").await?);
+
+    let order = reader.read_stream("order-1", 1, 10).await?;
+    assert_eq!(
+        vec![(2, "paid"), (3, "shipped")],
+        order
+            .iter()
+            .map(|event| (event.version, event.name.as_str()))
+            .collect::<Vec<_>>()
+    );
+
+    let all = reader.read_all(0, 10).await?;
+    assert_eq!(
+        vec!["order-1", "order-1", "order-2", "order-1"],
+        all.iter()
+            .map(|event| event.stream_id.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert!(all
+        .windows(2)
+        .all(|pair| pair[0].position < pair[1].position));
+    assert_eq!(all[2..], reader.read_all(all[1].position, 10).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_wrong_expected_version_rolls_back_business_transaction(
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+    append(
+        &store,
+        "user-1",
+        ExpectedVersion::NoStream,
+        vec![event("registered")],
+    )
+    .await?;
+
+    let result = users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(Uuid::new_v4(), "Alice".to_string())
+                    .await?;
+                users
+                    .chain(&store, |mut store| {
+                        Box::pin(async move {
+                            store
+                                .append("user-1", ExpectedVersion::NoStream, vec![event("renamed")])
+                                .await?;
+                            Ok(store)
+                        })
+                    })
+                    .await
+            })
+        })
+        .await;
+
+    let error = result.expect_err("the stream already exists");
+    assert_eq!(
+        Some(&EventStoreError::WrongExpectedVersion {
+            stream_id: "user-1".to_string(),
+            expected: ExpectedVersion::NoStream,
+            actual: 1,
+        }),
+        error.domain::<EventStoreError>()
+    );
+    assert!(UsersRepository::new(pool).get_users(10).await?.is_empty());
+    Ok(())
+}
+
+/// Begins an append to `account-1` that commits 200ms after taking the append lock.
+async fn hold_append(store: &EventStoreRepository<PgPool>) -> tokio::task::JoinHandle<()> {
+    let store = store.clone();
+    let (locked, wait) = oneshot::channel();
+    let holder = tokio::spawn(async move {
+        append_holding(&store, locked).await.expect("first append");
+    });
+    wait.await.expect("first append locked");
+    holder
+}
+
+async fn append_holding(
+    store: &EventStoreRepository<PgPool>,
+    locked: oneshot::Sender<()>,
+) -> Result<(), TxError> {
+    store
+        .begin(|mut store| {
+            Box::pin(async move {
+                store
+                    .append(
+                        "account-1",
+                        ExpectedVersion::NoStream,
+                        vec![event("opened")],
+                    )
+                    .await?;
+                let _ = locked.send(());
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok(store)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_concurrent_append_sees_committed_version(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool);
+    let holder = hold_append(&store).await;
+
+    let error = append(
+        &store,
+        "account-1",
+        ExpectedVersion::NoStream,
+        vec![event("opened")],
+    )
+    .await
+    .expect_err("the first append committed");
+    holder.await?;
+
+    assert!(matches!(
+        error.domain::<EventStoreError>(),
+        Some(EventStoreError::WrongExpectedVersion { actual: 1, .. })
+    ));
+    assert_eq!(1, store.clone().read_all(0, 10).await?.len());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_concurrent_append_with_stale_snapshot_conflicts(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool);
+    let holder = hold_append(&store).await;
+
+    let options = TxOptions::new().isolation(IsolationLevel::RepeatableRead);
+    let error = store
+        .begin_with(options, |mut store| {
+            Box::pin(async move {
+                store
+                    .append(
+                        "account-1",
+                        ExpectedVersion::NoStream,
+                        vec![event("opened")],
+                    )
+                    .await?;
+                Ok(store)
+            })
+        })
+        .await
+        .expect_err("the first append committed");
+    holder.await?;
+
+    assert_eq!(
+        Some(&EventStoreError::ConcurrentAppend),
+        error.domain::<EventStoreError>()
+    );
+    assert_eq!(1, store.clone().read_all(0, 10).await?.len());
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+use tx_chainable::{Begin, Chainable, DbErrorKind, FaultSchedule, Faulty, Tx, TxError, TxOptions};
+use tx_chainable_integration::{EventsRepository, UsersError, UsersRepository};
+use uuid::Uuid;
+
+/// Creates an event, then a user in a chain, then a second event.
+async fn create_event_and_user(
+    events_repo: &Faulty<EventsRepository<PgPool>>,
+    users_repo: &UsersRepository<PgPool>,
+) -> Result<(), TxError> {
+    events_repo
+        .begin(|mut events| {
+            Box::pin(async move {
+                events
+                    .create_event(Uuid::new_v4(), "first".to_string(), serde_json::json!({}))
+                    .await?;
+                let mut events = events
+                    .chain(users_repo, |mut users| {
+                        Box::pin(async move {
+                            users
+                                .create_user(Uuid::new_v4(), "Faulty".to_string())
+                                .await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                events
+                    .create_event(Uuid::new_v4(), "second".to_string(), serde_json::json!({}))
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await
+}
+
+async fn count_rows(pool: &PgPool) -> sqlx::Result<(i64, i64)> {
+    let events = sqlx::query_scalar("SELECT count(*) FROM events")
+        .fetch_one(pool)
+        .await?;
+    let users = sqlx::query_scalar("SELECT count(*) FROM users")
+        .fetch_one(pool)
+        .await?;
+    Ok((events, users))
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_failed_statement_mid_chain_rolls_back(pool: PgPool) -> anyhow::Result<()> {
+    let schedule = FaultSchedule::new().fail_statement(2, DbErrorKind::SerializationFailure);
+    let events_repo = Faulty::new(EventsRepository::new(pool.clone()), schedule);
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let error = create_event_and_user(&events_repo, &users_repo)
+        .await
+        .expect_err("statement 2 fails");
+
+    assert_eq!(Some(DbErrorKind::SerializationFailure), error.kind());
+    assert_eq!((0, 0), count_rows(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_injected_constraint_violation_maps_to_domain_error(
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    let schedule = FaultSchedule::new().fail_statement(
+        1,
+        DbErrorKind::UniqueViolation {
+            constraint: Some("users_pkey".to_string()),
+        },
+    );
+    let users_repo = Faulty::new(UsersRepository::new(pool), schedule);
+
+    let error = users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users.create_user(Uuid::new_v4(), "New".to_string()).await?;
+                Ok(users)
+            })
+        })
+        .await
+        .expect_err("statement 1 fails");
+
+    assert!(matches!(
+        error.domain::<UsersError>(),
+        Some(UsersError::AlreadyExists)
+    ));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_failed_commit_rolls_back(pool: PgPool) -> anyhow::Result<()> {
+    let schedule = FaultSchedule::new().fail_commit(DbErrorKind::Deadlock);
+    let events_repo = Faulty::new(EventsRepository::new(pool.clone()), schedule);
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let error = create_event_and_user(&events_repo, &users_repo)
+        .await
+        .expect_err("commit fails");
+
+    assert_eq!(Some(DbErrorKind::Deadlock), error.kind());
+    assert_eq!((0, 0), count_rows(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_dropped_connection_is_connection_lost(pool: PgPool) -> anyhow::Result<()> {
+    let schedule = FaultSchedule::new().drop_connection(2);
+    let events_repo = Faulty::new(EventsRepository::new(pool.clone()), schedule);
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let error = create_event_and_user(&events_repo, &users_repo)
+        .await
+        .expect_err("connection is dropped");
+
+    assert_eq!(Some(DbErrorKind::ConnectionLost), error.kind());
+    // The pool replaces the dead connection
+    assert_eq!((0, 0), count_rows(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_latency_delays_every_statement(pool: PgPool) -> anyhow::Result<()> {
+    let schedule = FaultSchedule::new().latency(Duration::from_millis(50));
+    let events_repo = Faulty::new(EventsRepository::new(pool.clone()), schedule);
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let start = Instant::now();
+    create_event_and_user(&events_repo, &users_repo).await?;
+
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!((2, 1), count_rows(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_faulty_is_named_after_its_repository(pool: PgPool) -> anyhow::Result<()> {
+    assert_eq!(
+        <EventsRepository<PgPool> as Tx>::name(),
+        <Faulty<EventsRepository<PgPool>> as Tx>::name()
+    );
+
+    let events_repo = Faulty::new(EventsRepository::new(pool), FaultSchedule::new());
+    let (result, report) = events_repo
+        .begin_with_report(TxOptions::new(), |mut events| {
+            Box::pin(async move {
+                events
+                    .create_event(Uuid::new_v4(), "named".to_string(), serde_json::json!({}))
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await;
+    result?;
+    assert_eq!("EventsRepository", report.statements[0].repository);
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use sqlx::PgPool;
+use tx_chainable::fixtures::FixtureError;
+use tx_chainable::DbErrorKind;
+use tx_chainable_integration::{fixtures, EventsRepository, User, UsersRepository};
+
+async fn counts(pool: &PgPool) -> sqlx::Result<(i64, i64)> {
+    sqlx::query_as("SELECT (SELECT count(*) FROM users), (SELECT count(*) FROM events)")
+        .fetch_one(pool)
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_yaml_fixtures_resolve_references(pool: PgPool) -> anyhow::Result<()> {
+    let seeded = fixtures::loader(&pool)
+        .load_yaml(
+            r#"
+users:
+  - $alias: alice
+    id: 6f1c2a0e-5b7d-4c11-9a43-0d3b2e8f7a10
+    name: Alice
+  - id: 1d4f7c2b-3e6a-4b8d-9c0e-5a7b2d4f6e81
+    name: Bob
+events:
+  - $alias: signup
+    id: 0b9e4d52-8c3f-4a6e-b1d7-2f5a9c8e6b34
+    name: signup
+    payload:
+      user_id: { $ref: alice.id }
+      user: { $ref: alice }
+"#,
+        )
+        .await?;
+
+    let alice: User = seeded.get("alice").expect("alice is aliased");
+    assert_eq!("Alice", alice.name);
+    assert_eq!(2, seeded.len());
+
+    let users = UsersRepository::new(pool.clone()).get_users(10).await?;
+    assert_eq!(2, users.len());
+    let events = EventsRepository::new(pool).get_events(10).await?;
+    assert_eq!(
+        serde_json::json!({
+            "user_id": alice.id,
+            "user": { "id": alice.id, "name": "Alice" },
+        }),
+        events[0].payload
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_failing_row_rolls_back_document(pool: PgPool) -> anyhow::Result<()> {
+    let error = fixtures::loader(&pool)
+        .load_json(
+            r#"{
+                "users": [
+                    { "id": "6f1c2a0e-5b7d-4c11-9a43-0d3b2e8f7a10", "name": "Alice" }
+                ],
+                "events": [
+                    { "id": "0b9e4d52-8c3f-4a6e-b1d7-2f5a9c8e6b34", "name": "a", "payload": {} },
+                    { "id": "0b9e4d52-8c3f-4a6e-b1d7-2f5a9c8e6b34", "name": "b", "payload": {} }
+                ]
+            }"#,
+        )
+        .await
+        .expect_err("the duplicate event fails");
+
+    let FixtureError::Transaction(error) = error else {
+        panic!("expected a transaction error, got {error}");
+    };
+    assert_eq!(
+        Some(DbErrorKind::UniqueViolation {
+            constraint: Some("events_pkey".to_string())
+        }),
+        error.kind()
+    );
+    assert_eq!((0, 0), counts(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_unknown_alias_rolls_back_document(pool: PgPool) -> anyhow::Result<()> {
+    let error = fixtures::loader(&pool)
+        .load_yaml(
+            r#"
+users:
+  - id: 6f1c2a0e-5b7d-4c11-9a43-0d3b2e8f7a10
+    name: Alice
+events:
+  - id: 0b9e4d52-8c3f-4a6e-b1d7-2f5a9c8e6b34
+    name: signup
+    payload:
+      user_id: { $ref: carol.id }
+"#,
+        )
+        .await
+        .expect_err("carol is not aliased");
+
+    assert!(matches!(error, FixtureError::UnknownAlias(alias) if alias == "carol"));
+    assert_eq!((0, 0), counts(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_invalid_documents_are_rejected(pool: PgPool) -> anyhow::Result<()> {
+    let loader = fixtures::loader(&pool);
+
+    let error = loader.load_yaml("accounts: []").await.unwrap_err();
+    assert!(matches!(error, FixtureError::UnknownRepository(key) if key == "accounts"));
+
+    let error = loader
+        .load_yaml("users:\n  - id: 6f1c2a0e-5b7d-4c11-9a43-0d3b2e8f7a10\n")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        FixtureError::InvalidRow { ref repository, index: 0, .. } if repository == "users"
+    ));
+
+    assert!(loader.load_yaml("{}").await?.is_empty());
+    assert_eq!((0, 0), counts(&pool).await?);
+    Ok(())
+}
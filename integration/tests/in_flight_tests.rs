@@ -0,0 +1,109 @@
+use std::time::Duration;
+use tx_chainable::{Begin, DbErrorKind, InFlightRegistry, InFlightTx, TxOptions, Watchdog};
+use tx_chainable_integration::UsersRepository;
+use uuid::Uuid;
+
+async fn wait_for_statement(name: &str) -> InFlightTx {
+    for _ in 0..100 {
+        let open = InFlightRegistry::snapshot()
+            .into_iter()
+            .find(|tx| tx.name == name && tx.last_statement.is_some());
+        if let Some(tx) = open {
+            return tx;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("transaction {name} never ran a statement");
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_cancel_blocked_transaction(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    InFlightRegistry::enable();
+    let user_id = Uuid::new_v4();
+
+    // Hold the row lock so the transaction under test blocks on its insert
+    let mut blocker = pool.begin().await?;
+    sqlx::query("INSERT INTO users (id, name) VALUES ($1, 'Blocker')")
+        .bind(user_id)
+        .execute(&mut *blocker)
+        .await?;
+
+    let users_repo = UsersRepository::new(pool.clone());
+    let blocked = tokio::spawn(async move {
+        users_repo
+            .begin_with(TxOptions::new().name("blocked_insert"), |mut users| {
+                Box::pin(async move {
+                    users.create_user(user_id, "Blocked".to_string()).await?;
+                    Ok(users)
+                })
+            })
+            .await
+    });
+
+    let open = wait_for_statement("blocked_insert").await;
+    assert!(open.backend_pid.is_some());
+    assert_eq!("UsersRepository", open.chain_path);
+    assert_eq!(
+        Some("INSERT INTO users (id, name) VALUES ($1, $2) RETURNING id, name"),
+        open.last_statement.as_deref()
+    );
+    assert!(Watchdog::new(Duration::ZERO)
+        .check()
+        .iter()
+        .any(|tx| tx.id == open.id));
+
+    assert!(InFlightRegistry::cancel(open.id, &pool).await?);
+
+    let error = blocked
+        .await?
+        .expect_err("canceled transaction should fail");
+    assert_eq!(Some(DbErrorKind::ConnectionLost), error.kind());
+    assert!(InFlightRegistry::snapshot()
+        .iter()
+        .all(|tx| tx.id != open.id));
+    assert!(!InFlightRegistry::cancel(open.id, &pool).await?);
+
+    blocker.rollback().await?;
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_cancel_idle_in_transaction(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    InFlightRegistry::enable();
+    let user_id = Uuid::new_v4();
+    let (resume, resumed) = tokio::sync::oneshot::channel::<()>();
+
+    let users_repo = UsersRepository::new(pool.clone());
+    let idle = tokio::spawn(async move {
+        users_repo
+            .begin_with(TxOptions::new().name("idle_insert"), |mut users| {
+                Box::pin(async move {
+                    users.create_user(user_id, "Idle".to_string()).await?;
+                    // Idle in transaction, holding the row lock, until resumed
+                    let _ = resumed.await;
+                    users.get_users(1).await?;
+                    Ok(users)
+                })
+            })
+            .await
+    });
+
+    let open = wait_for_statement("idle_insert").await;
+    assert!(InFlightRegistry::cancel(open.id, &pool).await?);
+
+    // The row lock is released without the transaction running another statement
+    let mut other = pool.begin().await?;
+    sqlx::query("SET LOCAL lock_timeout = '5s'")
+        .execute(&mut *other)
+        .await?;
+    sqlx::query("INSERT INTO users (id, name) VALUES ($1, 'Other')")
+        .bind(user_id)
+        .execute(&mut *other)
+        .await?;
+    other.rollback().await?;
+
+    resume.send(()).ok();
+    let error = idle.await?.expect_err("canceled transaction should fail");
+    assert_eq!(Some(DbErrorKind::ConnectionLost), error.kind());
+    Ok(())
+}
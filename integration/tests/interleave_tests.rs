@@ -0,0 +1,206 @@
+use sqlx::PgPool;
+use tx_chainable::{Begin, Interleaving, IsolationLevel, Schedule, TxError};
+use tx_chainable_integration::UsersRepository;
+use uuid::Uuid;
+
+const FIRST: Uuid = Uuid::from_u128(1);
+const SECOND: Uuid = Uuid::from_u128(2);
+
+async fn reset(pool: &PgPool, users: &[(Uuid, &str)]) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM users").execute(pool).await?;
+    for (id, name) in users {
+        sqlx::query("INSERT INTO users (id, name) VALUES ($1, $2)")
+            .bind(id)
+            .bind(name)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+async fn name_of(pool: &PgPool, id: Uuid) -> sqlx::Result<String> {
+    sqlx::query_scalar("SELECT name FROM users WHERE id = $1")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Appends `suffix` to the name of user `FIRST` after reading it.
+async fn append(
+    users_repo: &UsersRepository<PgPool>,
+    schedule: &Schedule,
+    label: &'static str,
+) -> Result<(), TxError> {
+    users_repo
+        .begin_with(schedule.options(label), |mut users| {
+            Box::pin(async move {
+                let current = users.get_users(10).await?.remove(0).name;
+                users
+                    .rename_user(FIRST, format!("{current}+{label}"))
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_lost_update_needs_repeatable_read(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    // Both read, both write (b blocks on a's row lock), then both commit
+    let report = Interleaving::new(["a", "b", "a", "b", "a", "b"])
+        .run(IsolationLevel::ALL, |schedule| {
+            let pool = pool.clone();
+            let users_repo = users_repo.clone();
+            async move {
+                reset(&pool, &[(FIRST, "x")])
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let (a, b) = tokio::join!(
+                    append(&users_repo, &schedule, "a"),
+                    append(&users_repo, &schedule, "b")
+                );
+                let name = name_of(&pool, FIRST).await.map_err(|e| e.to_string())?;
+                match (a, b) {
+                    (Ok(()), Ok(())) if name != "x+a+b" && name != "x+b+a" => {
+                        Err(format!("lost update, name is {name}"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        })
+        .await;
+
+    assert!(
+        !report.is_safe_at(IsolationLevel::ReadCommitted),
+        "{report}"
+    );
+    assert_eq!(
+        Some(IsolationLevel::RepeatableRead),
+        report.weakest_safe(),
+        "{report}"
+    );
+    Ok(())
+}
+
+/// Takes user `id` off call if someone else stays on call.
+async fn go_off_call(
+    users_repo: &UsersRepository<PgPool>,
+    schedule: &Schedule,
+    label: &'static str,
+    id: Uuid,
+) -> Result<(), TxError> {
+    users_repo
+        .begin_with(schedule.options(label), |mut users| {
+            Box::pin(async move {
+                let on_call = users
+                    .get_users(10)
+                    .await?
+                    .iter()
+                    .filter(|user| user.name == "on-call")
+                    .count();
+                if on_call >= 2 {
+                    users.rename_user(id, "off-call".to_string()).await?;
+                }
+                Ok(users)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_write_skew_needs_serializable(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let report = Interleaving::new(["a", "b", "a", "b", "a", "b"])
+        .run(IsolationLevel::ALL, |schedule| {
+            let pool = pool.clone();
+            let users_repo = users_repo.clone();
+            async move {
+                reset(&pool, &[(FIRST, "on-call"), (SECOND, "on-call")])
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let _ = tokio::join!(
+                    go_off_call(&users_repo, &schedule, "a", FIRST),
+                    go_off_call(&users_repo, &schedule, "b", SECOND)
+                );
+                let on_call: i64 =
+                    sqlx::query_scalar("SELECT count(*) FROM users WHERE name = 'on-call'")
+                        .fetch_one(&pool)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                match on_call {
+                    0 => Err("write skew, nobody is on call".to_string()),
+                    _ => Ok(()),
+                }
+            }
+        })
+        .await;
+
+    assert!(
+        !report.is_safe_at(IsolationLevel::ReadCommitted),
+        "{report}"
+    );
+    assert!(
+        !report.is_safe_at(IsolationLevel::RepeatableRead),
+        "{report}"
+    );
+    assert_eq!(
+        Some(IsolationLevel::Serializable),
+        report.weakest_safe(),
+        "{report}"
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_phantom_read_needs_repeatable_read(pool: PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    // a counts, b inserts and commits, a counts again
+    let report = Interleaving::new(["a", "b", "b", "a", "a"])
+        .run(IsolationLevel::ALL, |schedule| {
+            let pool = pool.clone();
+            let users_repo = users_repo.clone();
+            async move {
+                reset(&pool, &[(FIRST, "existing")])
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let counts = std::sync::Mutex::new(Vec::new());
+                let count = users_repo.begin_with(schedule.options("a"), |mut users| {
+                    let counts = &counts;
+                    Box::pin(async move {
+                        for _ in 0..2 {
+                            let users_seen = users.get_users(10).await?.len();
+                            counts.lock().unwrap().push(users_seen);
+                        }
+                        Ok(users)
+                    })
+                });
+                let insert = users_repo.begin_with(schedule.options("b"), |mut users| {
+                    Box::pin(async move {
+                        users.create_user(SECOND, "phantom".to_string()).await?;
+                        Ok(users)
+                    })
+                });
+                let (a, b) = tokio::join!(count, insert);
+                a.and(b).map_err(|e| e.to_string())?;
+                match counts.into_inner().unwrap()[..] {
+                    [first, second] if first != second => {
+                        Err(format!("phantom read, saw {first} then {second} users"))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        })
+        .await;
+
+    assert!(
+        !report.is_safe_at(IsolationLevel::ReadCommitted),
+        "{report}"
+    );
+    assert_eq!(
+        Some(IsolationLevel::RepeatableRead),
+        report.weakest_safe(),
+        "{report}"
+    );
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tx_chainable::listener::{Listener, ListenerError};
+use tx_chainable::{Begin, TxError};
+use tx_chainable_integration::{Event, EventAppended, EventStoreRepository, ExpectedVersion};
+use uuid::Uuid;
+
+fn event() -> Event {
+    Event {
+        id: Uuid::new_v4(),
+        name: "deposited".to_string(),
+        payload: serde_json::json!({ "amount": 10 }),
+    }
+}
+
+async fn append(pool: &PgPool, stream_id: &str, fail: bool) -> Result<(), TxError> {
+    EventStoreRepository::new(pool.clone())
+        .begin(|mut store| {
+            Box::pin(async move {
+                store
+                    .append(stream_id, ExpectedVersion::Any, vec![event(), event()])
+                    .await?;
+                if fail {
+                    return Err(TxError::Domain("rejected".into()));
+                }
+                Ok(store)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_notifications_are_delivered_on_commit_only(pool: PgPool) -> anyhow::Result<()> {
+    let mut listener = Listener::<EventAppended>::connect(&pool, &[EventAppended::CHANNEL]).await?;
+
+    assert!(append(&pool, "rolled-back", true).await.is_err());
+    append(&pool, "committed", false).await?;
+
+    let notification = timeout(Duration::from_secs(5), listener.recv()).await??;
+    assert_eq!(EventAppended::CHANNEL, notification.channel);
+    assert_eq!(
+        EventAppended {
+            stream_id: "committed".to_string(),
+            version: 2,
+            position: notification.payload.position,
+        },
+        notification.payload
+    );
+    assert!(timeout(Duration::from_millis(200), listener.recv())
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_undecodable_payload_leaves_listener_usable(pool: PgPool) -> anyhow::Result<()> {
+    let mut listener = Listener::<EventAppended>::connect(&pool, &[EventAppended::CHANNEL]).await?;
+
+    sqlx::query("SELECT pg_notify($1, 'not json')")
+        .bind(EventAppended::CHANNEL)
+        .execute(&pool)
+        .await?;
+    append(&pool, "after", false).await?;
+
+    match timeout(Duration::from_secs(5), listener.recv()).await? {
+        Err(ListenerError::Payload { payload, .. }) => assert_eq!("not json", payload),
+        other => panic!("expected a payload error, got {other:?}"),
+    }
+    let notification = timeout(Duration::from_secs(5), listener.recv()).await??;
+    assert_eq!("after", notification.payload.stream_id);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_reconnects_and_catches_up_after_connection_loss(pool: PgPool) -> anyhow::Result<()> {
+    let (caught_up, mut catch_ups) = mpsc::unbounded_channel();
+    let mut listener = Listener::<EventAppended>::connect(&pool, &[EventAppended::CHANNEL])
+        .await?
+        .reconnect_delay(Duration::from_millis(10), Duration::from_millis(50))
+        .on_reconnect(move || {
+            let caught_up = caught_up.clone();
+            async move {
+                caught_up.send(()).ok();
+                Ok(())
+            }
+        });
+
+    let terminated: Vec<bool> = sqlx::query_scalar(
+        "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+         WHERE datname = current_database() AND pid <> pg_backend_pid() AND query LIKE 'LISTEN%'",
+    )
+    .fetch_all(&pool)
+    .await?;
+    assert_eq!(vec![true], terminated);
+
+    let receiving = tokio::spawn(async move { listener.recv().await });
+    timeout(Duration::from_secs(5), catch_ups.recv()).await?;
+    append(&pool, "after-reconnect", false).await?;
+
+    let notification = timeout(Duration::from_secs(5), receiving).await???;
+    assert_eq!("after-reconnect", notification.payload.stream_id);
+    assert!(catch_ups.try_recv().is_err());
+    Ok(())
+}
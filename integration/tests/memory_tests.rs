@@ -0,0 +1,109 @@
+use tx_chainable::{Begin, Chainable, DbErrorKind, MemoryStore, TxError};
+use tx_chainable_integration::{MemoryEventsRepository, MemoryUsersRepository, UsersError};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_staged_writes_are_visible_only_inside_transaction() -> anyhow::Result<()> {
+    let store = MemoryStore::new();
+    let events_repo = MemoryEventsRepository::new(store.clone());
+    let users_repo = MemoryUsersRepository::new(store.clone());
+    let users_repo2 = users_repo.clone();
+    let committed = store.clone();
+    let user_id = Uuid::new_v4();
+
+    events_repo
+        .begin(|events| {
+            Box::pin(async move {
+                let events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            users.create_user(user_id, "Staged".to_string()).await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                let events = events
+                    .chain(&users_repo2, |mut users| {
+                        Box::pin(async move {
+                            // The same transaction sees its own write
+                            assert_eq!(1, users.get_users(10).await?.len());
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                // A repository on the store itself does not
+                let mut committed = MemoryUsersRepository::new(committed);
+                assert!(committed.get_users(10).await?.is_empty());
+                Ok(events)
+            })
+        })
+        .await?;
+
+    let users = MemoryUsersRepository::new(store).get_users(10).await?;
+    assert_eq!(
+        vec![user_id],
+        users.iter().map(|u| u.id).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_constraint_violation_maps_to_domain_error() -> anyhow::Result<()> {
+    let store = MemoryStore::new();
+    let users_repo = MemoryUsersRepository::new(store.clone());
+    let user_id = Uuid::new_v4();
+    MemoryUsersRepository::new(store)
+        .create_user(user_id, "Existing".to_string())
+        .await?;
+
+    let error = users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users.create_user(user_id, "Duplicate".to_string()).await?;
+                Ok(users)
+            })
+        })
+        .await
+        .expect_err("duplicate user id should fail");
+
+    assert!(matches!(
+        error.domain::<UsersError>(),
+        Some(UsersError::AlreadyExists)
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_conflicting_commit_is_a_serialization_failure() -> anyhow::Result<()> {
+    let store = MemoryStore::new();
+    let users_repo = MemoryUsersRepository::new(store.clone());
+
+    let result = users_repo
+        .begin(|mut users| {
+            let store = store.clone();
+            Box::pin(async move {
+                users
+                    .create_user(Uuid::new_v4(), "Staged".to_string())
+                    .await?;
+                // Someone else commits to the same table before this transaction does
+                MemoryUsersRepository::new(store)
+                    .create_user(Uuid::new_v4(), "Concurrent".to_string())
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await;
+
+    match result {
+        Err(error @ TxError::Database(_)) => {
+            assert_eq!(Some(DbErrorKind::SerializationFailure), error.kind())
+        }
+        result => panic!("expected a serialization failure, got {result:?}"),
+    }
+    let users = MemoryUsersRepository::new(store).get_users(10).await?;
+    assert_eq!(
+        vec!["Concurrent"],
+        users.iter().map(|u| u.name.as_str()).collect::<Vec<_>>()
+    );
+    Ok(())
+}
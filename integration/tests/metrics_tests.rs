@@ -0,0 +1,192 @@
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshot};
+use metrics_util::CompositeKey;
+use tx_chainable::{Begin, Chainable, TxOptions};
+use tx_chainable_integration::{EventsRepository, UsersRepository};
+use uuid::Uuid;
+
+type Metrics = Vec<(CompositeKey, DebugValue)>;
+
+fn collect(snapshot: Snapshot) -> Metrics {
+    snapshot
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| (key, value))
+        .collect()
+}
+
+/// Returns the value of `name` whose labels include all of `labels`.
+fn value<'a>(recorded: &'a Metrics, name: &str, labels: &[(&str, &str)]) -> Option<&'a DebugValue> {
+    recorded
+        .iter()
+        .find(|(key, _)| {
+            key.key().name() == name
+                && labels.iter().all(|(k, v)| {
+                    key.key()
+                        .labels()
+                        .any(|label| label.key() == *k && label.value() == *v)
+                })
+        })
+        .map(|(_, value)| value)
+}
+
+fn histogram(values: &[f64]) -> DebugValue {
+    DebugValue::Histogram(values.iter().map(|v| (*v).into()).collect())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_committed_transaction_metrics(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+    let events_repo2 = events_repo.clone();
+    let user_id = Uuid::new_v4();
+
+    events_repo
+        .begin_with(TxOptions::new().name("register_user"), |events| {
+            Box::pin(async move {
+                let events = events
+                    .chain(&users_repo, |users| {
+                        Box::pin(async move {
+                            let mut users = users
+                                .chain(&events_repo2, |events| Box::pin(async move { Ok(events) }))
+                                .await?;
+                            users
+                                .create_user(user_id, "Metered User".to_string())
+                                .await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await?;
+
+    let recorded = collect(snapshotter.snapshot());
+    let transaction = [("transaction", "register_user")];
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value(
+            &recorded,
+            "tx_chainable_transactions_started_total",
+            &transaction
+        )
+    );
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value(
+            &recorded,
+            "tx_chainable_transactions_committed_total",
+            &transaction
+        )
+    );
+    assert_eq!(
+        None,
+        value(
+            &recorded,
+            "tx_chainable_transactions_rolled_back_total",
+            &transaction
+        )
+    );
+    assert_eq!(
+        Some(&DebugValue::Counter(2)),
+        value(&recorded, "tx_chainable_chain_hops_total", &transaction)
+    );
+    assert_eq!(
+        Some(&histogram(&[2.0])),
+        value(&recorded, "tx_chainable_chain_depth", &transaction)
+    );
+    assert_eq!(
+        Some(&histogram(&[2.0])),
+        value(&recorded, "tx_chainable_chain_hops", &transaction)
+    );
+    assert!(matches!(
+        value(&recorded, "tx_chainable_transaction_duration_seconds", &transaction),
+        Some(DebugValue::Histogram(durations)) if durations.len() == 1
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_failed_transaction_metrics(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let events_repo = EventsRepository::new(pool.clone());
+    let event_id = Uuid::new_v4();
+
+    let result = events_repo
+        .begin_with(TxOptions::new().name("duplicate_event"), |mut events| {
+            Box::pin(async move {
+                for _ in 0..2 {
+                    events
+                        .create_event(event_id, "duplicate".to_string(), serde_json::json!({}))
+                        .await?;
+                }
+                Ok(events)
+            })
+        })
+        .await;
+    assert!(result.is_err());
+
+    let recorded = collect(snapshotter.snapshot());
+    let transaction = [("transaction", "duplicate_event")];
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value(
+            &recorded,
+            "tx_chainable_transactions_rolled_back_total",
+            &transaction
+        )
+    );
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value(
+            &recorded,
+            "tx_chainable_transactions_failed_total",
+            &[
+                ("transaction", "duplicate_event"),
+                ("kind", "unique_violation")
+            ]
+        )
+    );
+    assert_eq!(
+        None,
+        value(
+            &recorded,
+            "tx_chainable_transactions_committed_total",
+            &transaction
+        )
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_transaction_name_defaults_to_repository(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let users_repo = UsersRepository::new(pool.clone());
+    users_repo
+        .begin(|users| Box::pin(async move { Ok(users) }))
+        .await?;
+
+    let recorded = collect(snapshotter.snapshot());
+    assert_eq!(
+        Some(&DebugValue::Counter(1)),
+        value(
+            &recorded,
+            "tx_chainable_transactions_committed_total",
+            &[("transaction", "UsersRepository")]
+        )
+    );
+
+    Ok(())
+}
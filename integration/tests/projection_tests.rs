@@ -0,0 +1,250 @@
+use sqlx::PgPool;
+use std::time::Duration;
+use tx_chainable::{Begin, Chainable, TxError};
+use tx_chainable_integration::projections::{ProjectionRunner, UserDirectory};
+use tx_chainable_integration::{
+    Event, EventStoreRepository, ExpectedVersion, SubscriptionError, SubscriptionRepository,
+    UsersRepository,
+};
+use uuid::Uuid;
+
+fn user_event(name: &str, id: Uuid, user_name: &str) -> Event {
+    Event {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        payload: serde_json::json!({ "id": id, "name": user_name }),
+    }
+}
+
+async fn append(pool: &PgPool, stream_id: &str, events: Vec<Event>) -> Result<(), TxError> {
+    EventStoreRepository::new(pool.clone())
+        .begin(|mut store| {
+            Box::pin(async move {
+                store
+                    .append(stream_id, ExpectedVersion::Any, events)
+                    .await?;
+                Ok(store)
+            })
+        })
+        .await
+}
+
+async fn user_names(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    Ok(sqlx::query_scalar("SELECT name FROM users ORDER BY name")
+        .fetch_all(pool)
+        .await?)
+}
+
+async fn checkpoint(pool: &PgPool) -> anyhow::Result<i64> {
+    Ok(SubscriptionRepository::new(pool.clone())
+        .checkpoint("user_directory")
+        .await?)
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_catch_up_applies_events_after_checkpoint(pool: PgPool) -> anyhow::Result<()> {
+    let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+    append(
+        &pool,
+        "user-alice",
+        vec![user_event("user_registered", alice, "alice")],
+    )
+    .await?;
+    append(
+        &pool,
+        "user-bob",
+        vec![user_event("user_registered", bob, "bob")],
+    )
+    .await?;
+
+    let runner =
+        ProjectionRunner::new(pool.clone(), UserDirectory::new(pool.clone())).batch_size(1);
+    assert_eq!(Some(2), runner.catch_up().await?);
+    assert_eq!(vec!["alice", "bob"], user_names(&pool).await?);
+    assert_eq!(2, checkpoint(&pool).await?);
+
+    append(
+        &pool,
+        "user-alice",
+        vec![user_event("user_renamed", alice, "carol")],
+    )
+    .await?;
+    assert_eq!(Some(1), runner.catch_up().await?);
+    assert_eq!(Some(0), runner.catch_up().await?);
+    assert_eq!(vec!["bob", "carol"], user_names(&pool).await?);
+    assert_eq!(3, checkpoint(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_failed_batch_keeps_checkpoint(pool: PgPool) -> anyhow::Result<()> {
+    let alice = Uuid::new_v4();
+    append(
+        &pool,
+        "user-alice",
+        vec![user_event("user_registered", alice, "alice")],
+    )
+    .await?;
+    append(
+        &pool,
+        "user-broken",
+        vec![Event {
+            id: Uuid::new_v4(),
+            name: "user_registered".to_string(),
+            payload: serde_json::json!({ "name": "no id" }),
+        }],
+    )
+    .await?;
+
+    let runner = ProjectionRunner::new(pool.clone(), UserDirectory::new(pool.clone()));
+    let error = runner.catch_up().await.unwrap_err();
+    assert!(error.domain::<serde_json::Error>().is_some());
+    assert!(user_names(&pool).await?.is_empty());
+    assert_eq!(0, checkpoint(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_concurrent_runners_apply_each_event_once(pool: PgPool) -> anyhow::Result<()> {
+    for i in 0..20 {
+        let id = Uuid::new_v4();
+        append(
+            &pool,
+            &format!("user-{i}"),
+            vec![user_event("user_registered", id, &format!("user {i:02}"))],
+        )
+        .await?;
+    }
+
+    let first = ProjectionRunner::new(pool.clone(), UserDirectory::new(pool.clone())).batch_size(3);
+    let second =
+        ProjectionRunner::new(pool.clone(), UserDirectory::new(pool.clone())).batch_size(3);
+    let (first, second) = tokio::join!(first.catch_up(), second.catch_up());
+
+    let mut applied = [first?, second?];
+    applied.sort();
+    assert_eq!([None, Some(20)], applied);
+    assert_eq!(20, user_names(&pool).await?.len());
+    assert_eq!(20, checkpoint(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_expired_lease_is_taken_over_and_fences_old_owner(pool: PgPool) -> anyhow::Result<()> {
+    let subscriptions = SubscriptionRepository::new(pool.clone());
+    let users = UsersRepository::new(pool.clone());
+    let (old_owner, new_owner) = (Uuid::new_v4(), Uuid::new_v4());
+    let ttl = Duration::from_secs(30);
+
+    assert!(
+        subscriptions
+            .clone()
+            .acquire_lease("user_directory", old_owner, ttl)
+            .await?
+    );
+    assert!(
+        !subscriptions
+            .clone()
+            .acquire_lease("user_directory", new_owner, ttl)
+            .await?
+    );
+
+    sqlx::query("UPDATE subscriptions SET lease_expires_at = now() - interval '1 second'")
+        .execute(&pool)
+        .await?;
+    assert!(
+        subscriptions
+            .clone()
+            .acquire_lease("user_directory", new_owner, ttl)
+            .await?
+    );
+
+    // The old owner's writes roll back with the batch its lease check fails in
+    let result = subscriptions
+        .begin(|subscriptions| {
+            Box::pin(async move {
+                let mut subscriptions = subscriptions
+                    .chain(&users, |mut users| {
+                        Box::pin(async move {
+                            users
+                                .create_user(Uuid::new_v4(), "stale".to_string())
+                                .await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                subscriptions.lock("user_directory", old_owner, ttl).await?;
+                Ok(subscriptions)
+            })
+        })
+        .await;
+    assert_eq!(
+        Some(&SubscriptionError::LeaseLost {
+            subscription: "user_directory".to_string()
+        }),
+        result.unwrap_err().domain::<SubscriptionError>()
+    );
+    assert!(user_names(&pool).await?.is_empty());
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_rebuild_replays_from_zero(pool: PgPool) -> anyhow::Result<()> {
+    let alice = Uuid::new_v4();
+    append(
+        &pool,
+        "user-alice",
+        vec![user_event("user_registered", alice, "alice")],
+    )
+    .await?;
+    let runner = ProjectionRunner::new(pool.clone(), UserDirectory::new(pool.clone()));
+    assert_eq!(Some(1), runner.catch_up().await?);
+
+    sqlx::query("UPDATE users SET name = 'drifted'")
+        .execute(&pool)
+        .await?;
+    append(
+        &pool,
+        "user-alice",
+        vec![user_event("user_renamed", alice, "alicia")],
+    )
+    .await?;
+
+    assert_eq!(Some(2), runner.rebuild().await?);
+    assert_eq!(vec!["alicia"], user_names(&pool).await?);
+    assert_eq!(2, checkpoint(&pool).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_run_polls_until_stopped_and_releases_lease(pool: PgPool) -> anyhow::Result<()> {
+    let runner = ProjectionRunner::new(pool.clone(), UserDirectory::new(pool.clone()))
+        .poll_interval(Duration::from_millis(20));
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let running = tokio::spawn(async move {
+        runner
+            .run(async {
+                let _ = stopped.await;
+            })
+            .await
+    });
+
+    append(
+        &pool,
+        "user-alice",
+        vec![user_event("user_registered", Uuid::new_v4(), "alice")],
+    )
+    .await?;
+    while user_names(&pool).await?.is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    stop.send(()).ok();
+    running.await??;
+
+    let lease_owner: Option<Uuid> =
+        sqlx::query_scalar("SELECT lease_owner FROM subscriptions WHERE name = 'user_directory'")
+            .fetch_one(&pool)
+            .await?;
+    assert_eq!(None, lease_owner);
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+use tx_chainable::{Begin, Chainable, Execute, GetExecutor, Transaction, Tx, TxOptions};
+use tx_chainable_integration::EventsRepository;
+
+/// Reports what the server sees for the statements it runs.
+struct ProbeRepository<E: Execute> {
+    executor: E,
+}
+
+impl<E: Execute> Tx for ProbeRepository<E> {
+    type TxRepository<'tx> = ProbeRepository<Transaction<'tx>>;
+}
+
+impl<'tx> GetExecutor<'tx> for ProbeRepository<PgPool> {
+    type Executor = &'tx PgPool;
+    fn get_executor(&'tx self) -> Self::Executor {
+        &self.executor
+    }
+}
+
+impl<'tx> From<ProbeRepository<Transaction<'tx>>> for Transaction<'tx> {
+    fn from(repository: ProbeRepository<Transaction<'tx>>) -> Self {
+        repository.executor
+    }
+}
+
+impl<'tx> From<Transaction<'tx>> for ProbeRepository<Transaction<'tx>> {
+    fn from(tx: Transaction<'tx>) -> Self {
+        Self { executor: tx }
+    }
+}
+
+impl ProbeRepository<PgPool> {
+    fn new(pool: PgPool) -> Self {
+        Self { executor: pool }
+    }
+}
+
+impl<E: Execute> ProbeRepository<E> {
+    async fn current_query(&mut self) -> Result<String, sqlx::Error> {
+        self.executor
+            .execute(|e| sqlx::query_scalar("SELECT current_query()").fetch_one(e))
+            .await
+    }
+
+    async fn application_name(&mut self) -> Result<String, sqlx::Error> {
+        self.executor
+            .execute(|e| {
+                sqlx::query_scalar("SELECT current_setting('application_name')").fetch_one(e)
+            })
+            .await
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_statements_are_tagged(pool: PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let probe_repo = ProbeRepository::new(pool.clone());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let seen_in_tx = seen.clone();
+    let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    let options = TxOptions::new()
+        .name("register_user")
+        .tag_queries(true)
+        .traceparent(traceparent);
+    events_repo
+        .begin_with(options, |events| {
+            Box::pin(async move {
+                let events = events
+                    .chain(&probe_repo, |mut probe| {
+                        Box::pin(async move {
+                            let query = probe.current_query().await?;
+                            let application_name = probe.application_name().await?;
+                            seen_in_tx.lock().unwrap().extend([query, application_name]);
+                            Ok(probe)
+                        })
+                    })
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await?;
+
+    let seen = seen.lock().unwrap().clone();
+    assert_eq!(
+        vec![
+            format!(
+                "SELECT current_query() /*chain='EventsRepository%20-%3E%20ProbeRepository',\
+                 repository='ProbeRepository',traceparent='{traceparent}',\
+                 transaction='register_user'*/"
+            ),
+            "register_user".to_string(),
+        ],
+        seen
+    );
+
+    // application_name only lasts for the transaction
+    let application_name = ProbeRepository::new(pool).application_name().await?;
+    assert_ne!("register_user", application_name);
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_statements_are_untagged_by_default(pool: PgPool) -> anyhow::Result<()> {
+    let probe_repo = ProbeRepository::new(pool.clone());
+    let seen = Arc::new(Mutex::new(None));
+    let seen_in_tx = seen.clone();
+
+    probe_repo
+        .begin(|mut probe| {
+            Box::pin(async move {
+                *seen_in_tx.lock().unwrap() = Some(probe.current_query().await?);
+                Ok(probe)
+            })
+        })
+        .await?;
+
+    assert_eq!(
+        Some("SELECT current_query()".to_string()),
+        seen.lock().unwrap().clone()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use futures_util::{StreamExt, TryStreamExt};
+use tx_chainable::{Begin, Chainable, Execute, Transaction, TxOptions};
+use tx_chainable_integration::{EventsRepository, UsersRepository};
+use uuid::Uuid;
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_report_lists_statements(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+    let event_id = Uuid::new_v4();
+
+    let (result, report) = events_repo
+        .begin_with_report(TxOptions::new(), |events| {
+            Box::pin(async move {
+                let mut events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            users
+                                .create_user(user_id, "Reported User".to_string())
+                                .await?;
+                            users.get_users(10).await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                events
+                    .create_event(event_id, "user_created".to_string(), serde_json::json!({}))
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await;
+    result?;
+
+    let statements: Vec<_> = report
+        .statements
+        .iter()
+        .map(|s| (s.repository, s.sql.as_str(), s.parameters, s.rows_returned))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "UsersRepository",
+                "INSERT INTO users (id, name) VALUES ($1, $2) RETURNING id, name",
+                2,
+                1
+            ),
+            (
+                "UsersRepository",
+                "SELECT id, name FROM users ORDER BY name, id LIMIT $1",
+                1,
+                1
+            ),
+            (
+                "EventsRepository",
+                "INSERT INTO events (id, name, payload) VALUES ($1, $2, $3) RETURNING id, name, payload",
+                3,
+                1
+            ),
+        ],
+        statements
+    );
+    assert_eq!(1, report.chain_hops);
+    assert!(report.connection_held >= report.statements.iter().map(|s| s.duration).sum());
+    assert!(report.wal_bytes.is_some_and(|bytes| bytes > 0));
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_report_of_failed_transaction(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    let (result, report) = users_repo
+        .begin_with_report(TxOptions::new(), |mut users| {
+            Box::pin(async move {
+                users.create_user(user_id, "First".to_string()).await?;
+                users.create_user(user_id, "Second".to_string()).await?;
+                Ok(users)
+            })
+        })
+        .await;
+    assert!(result.is_err());
+
+    // The failing statement is reported, without any rows
+    assert_eq!(2, report.statements.len());
+    assert_eq!(0, report.statements[1].rows_returned);
+    assert_eq!(None, report.wal_bytes);
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_report_streams_rows(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+
+    let (result, report) = users_repo
+        .begin_with_report(TxOptions::new(), |users| {
+            Box::pin(async move {
+                let mut tx: Transaction = users.into();
+                // Only the rows taken are fetched; the rest is never buffered
+                let first: Vec<i32> = tx
+                    .execute(|e| {
+                        sqlx::query_scalar::<_, i32>("SELECT generate_series(1, 100000)")
+                            .fetch(e)
+                            .take(3)
+                            .try_collect()
+                    })
+                    .await?;
+                assert_eq!(vec![1, 2, 3], first);
+                Ok(UsersRepository::from(tx))
+            })
+        })
+        .await;
+    result?;
+
+    assert_eq!(1, report.statements.len());
+    assert_eq!(3, report.statements[0].rows_returned);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_report_counts_rows_affected_by_writes_only(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    let (result, report) = users_repo
+        .begin_with_report(TxOptions::new(), |mut users| {
+            Box::pin(async move {
+                // INSERT ... RETURNING and UPDATE ... RETURNING run through fetch_one/fetch_optional
+                users.create_user(user_id, "Counted".to_string()).await?;
+                users.rename_user(user_id, "Recounted".to_string()).await?;
+                users
+                    .rename_user(Uuid::new_v4(), "Nobody".to_string())
+                    .await?;
+                users.get_users(10).await?;
+                Ok(users)
+            })
+        })
+        .await;
+    result?;
+
+    let rows: Vec<_> = report
+        .statements
+        .iter()
+        .map(|s| (s.rows_affected, s.rows_returned))
+        .collect();
+    assert_eq!(vec![(1, 1), (1, 1), (0, 0), (0, 1)], rows);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_report_parameter_values_are_opt_in(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    let (result, report) = users_repo
+        .begin_with_report(
+            TxOptions::new().report_parameter_values(true),
+            |mut users| {
+                Box::pin(async move {
+                    users.create_user(user_id, "Valued".to_string()).await?;
+                    Ok(users)
+                })
+            },
+        )
+        .await;
+    result?;
+    assert_eq!(
+        Some(vec![Some(user_id.to_string()), Some("Valued".to_string())]),
+        report.statements[0].parameter_values
+    );
+
+    let (result, report) = users_repo
+        .begin_with_report(TxOptions::new(), |mut users| {
+            Box::pin(async move {
+                users.get_users(10).await?;
+                Ok(users)
+            })
+        })
+        .await;
+    result?;
+    assert_eq!(None, report.statements[0].parameter_values);
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use sqlx::PgPool;
+use tx_chainable::{testing, Begin, Chainable};
+use tx_chainable_integration::{EventsRepository, UsersRepository};
+use uuid::Uuid;
+
+async fn user_exists(pool: &PgPool, id: Uuid) -> sqlx::Result<bool> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE id = $1)")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+}
+
+#[tx_chainable::test(migrations = "./migrations")]
+async fn test_begin_runs_as_savepoint(
+    events_repo: EventsRepository<PgPool>,
+    users_repo: UsersRepository<PgPool>,
+    pool: PgPool,
+) -> anyhow::Result<()> {
+    let outer: i64 = sqlx::query_scalar("SELECT txid_current()")
+        .fetch_one(&pool)
+        .await?;
+    let kept_id = Uuid::new_v4();
+    let discarded_id = Uuid::new_v4();
+
+    events_repo
+        .begin(|events| {
+            Box::pin(async move {
+                let mut events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            users.create_user(kept_id, "Kept".to_string()).await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                events
+                    .create_event(Uuid::new_v4(), "kept".to_string(), serde_json::json!({}))
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await?;
+
+    let users_repo = UsersRepository::new(pool.clone());
+    let result = users_repo
+        .begin(|mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(discarded_id, "Discarded".to_string())
+                    .await?;
+                Err(sqlx::Error::RowNotFound.into())
+            })
+        })
+        .await;
+    assert!(result.is_err());
+
+    // Only the failed savepoint was rolled back, all inside the outer transaction
+    assert!(user_exists(&pool, kept_id).await?);
+    assert!(!user_exists(&pool, discarded_id).await?);
+    let current: i64 = sqlx::query_scalar("SELECT txid_current()")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(outer, current);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_rollback_only_pool_discards_commits(pool: PgPool) -> anyhow::Result<()> {
+    let rollback_pool = testing::rollback_only((*pool.connect_options()).clone()).await?;
+    let user_id = Uuid::new_v4();
+
+    UsersRepository::new(rollback_pool.clone())
+        .begin(|mut users| {
+            Box::pin(async move {
+                users
+                    .create_user(user_id, "Rolled Back".to_string())
+                    .await?;
+                Ok(users)
+            })
+        })
+        .await?;
+    assert!(user_exists(&rollback_pool, user_id).await?);
+
+    testing::roll_back(rollback_pool).await?;
+    assert!(!user_exists(&pool, user_id).await?);
+    Ok(())
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tx_chainable::{Begin, Chainable, TxError};
+use tx_chainable_integration::{
+    Aggregate, Event, EventStoreRepository, ExpectedVersion, RecordedEvent, SnapshotPolicy,
+    SnapshotRepository,
+};
+use uuid::Uuid;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct Account {
+    balance: i64,
+}
+
+impl Aggregate for Account {
+    fn apply(&mut self, event: &RecordedEvent) {
+        self.balance += event.payload["amount"].as_i64().unwrap_or_default();
+    }
+}
+
+/// `Account` after its state changed shape.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct AccountInCents {
+    cents: i64,
+}
+
+impl Aggregate for AccountInCents {
+    const STATE_VERSION: i32 = 2;
+
+    fn apply(&mut self, event: &RecordedEvent) {
+        self.cents += event.payload["amount"].as_i64().unwrap_or_default() * 100;
+    }
+}
+
+const POLICY: SnapshotPolicy = SnapshotPolicy::Every(3);
+
+fn deposits(amounts: &[i64]) -> Vec<Event> {
+    amounts
+        .iter()
+        .map(|amount| Event {
+            id: Uuid::new_v4(),
+            name: "deposited".to_string(),
+            payload: serde_json::json!({ "amount": amount }),
+        })
+        .collect()
+}
+
+async fn deposit(store: &EventStoreRepository<PgPool>, amounts: &[i64]) -> Result<(), TxError> {
+    let events = deposits(amounts);
+    store
+        .begin(|mut store| {
+            Box::pin(async move {
+                store
+                    .append("account-1", ExpectedVersion::Any, events)
+                    .await?;
+                Ok(store)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_load_replays_only_events_after_snapshot(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool.clone());
+    let snapshots = SnapshotRepository::new(pool.clone());
+    deposit(&store, &[10, 20, 30, 40, 50]).await?;
+
+    snapshots
+        .begin(|mut snapshots| {
+            Box::pin(async move {
+                let mut account = snapshots.load::<Account>("account-1").await?;
+                assert_eq!(
+                    (150, 5, 0),
+                    (
+                        account.state.balance,
+                        account.version,
+                        account.snapshot_version
+                    )
+                );
+                assert!(snapshots.save_if_due(&mut account, POLICY).await?);
+                assert_eq!(5, account.snapshot_version);
+                Ok(snapshots)
+            })
+        })
+        .await?;
+    deposit(&store, &[1, 2]).await?;
+
+    // Events up to the snapshot are not replayed: the snapshot state is used as is
+    sqlx::query("UPDATE snapshots SET state = '{\"balance\": 1000}'")
+        .execute(&pool)
+        .await?;
+    let account = snapshots.clone().load::<Account>("account-1").await?;
+    assert_eq!(1003, account.state.balance);
+    assert_eq!((7, 5), (account.version, account.snapshot_version));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_snapshot_policy_and_newer_snapshots(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool.clone());
+    let mut snapshots = SnapshotRepository::new(pool);
+    deposit(&store, &[10, 20]).await?;
+
+    let mut account = snapshots.load::<Account>("account-1").await?;
+    assert!(!snapshots.save_if_due(&mut account, POLICY).await?);
+    deposit(&store, &[30]).await?;
+    let mut account = snapshots.load::<Account>("account-1").await?;
+    assert!(
+        !snapshots
+            .save_if_due(&mut account, SnapshotPolicy::Never)
+            .await?
+    );
+    assert_eq!(
+        0,
+        snapshots
+            .load::<Account>("account-1")
+            .await?
+            .snapshot_version
+    );
+
+    snapshots
+        .save("account-1", 2, &Account { balance: 30 })
+        .await?;
+    snapshots
+        .save("account-1", 1, &Account { balance: 10 })
+        .await?;
+    let account = snapshots.load::<Account>("account-1").await?;
+    assert_eq!((60, 2), (account.state.balance, account.snapshot_version));
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_stale_state_version_falls_back_to_replay(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool.clone());
+    let mut snapshots = SnapshotRepository::new(pool);
+    deposit(&store, &[10, 20, 30]).await?;
+    let mut account = snapshots.load::<Account>("account-1").await?;
+    assert!(snapshots.save_if_due(&mut account, POLICY).await?);
+
+    // The stored state has no `cents`, and is not decoded at all
+    let mut account = snapshots.load::<AccountInCents>("account-1").await?;
+    assert_eq!(
+        (6000, 3, 0),
+        (
+            account.state.cents,
+            account.version,
+            account.snapshot_version
+        )
+    );
+
+    // The stale snapshot is replaced even at the same stream version
+    assert!(snapshots.save_if_due(&mut account, POLICY).await?);
+    let account = snapshots.load::<AccountInCents>("account-1").await?;
+    assert_eq!((6000, 3), (account.state.cents, account.snapshot_version));
+    assert_eq!(
+        0,
+        snapshots
+            .load::<Account>("account-1")
+            .await?
+            .snapshot_version
+    );
+
+    // A writer still on the older state version does not overwrite it
+    deposit(&store, &[40, 50, 60]).await?;
+    let mut account = snapshots.load::<Account>("account-1").await?;
+    assert!(snapshots.save_if_due(&mut account, POLICY).await?);
+    let account = snapshots.load::<AccountInCents>("account-1").await?;
+    assert_eq!((21000, 3), (account.state.cents, account.snapshot_version));
+    Ok(())
+}
+
+/// Appends three deposits and snapshots the account in one transaction,
+/// failing after both if `fail` is set.
+async fn append_and_snapshot(
+    store: &EventStoreRepository<PgPool>,
+    snapshots: &SnapshotRepository<PgPool>,
+    fail: bool,
+) -> Result<(), TxError> {
+    let events = deposits(&[5, 5, 5]);
+    store
+        .begin(move |mut store| {
+            Box::pin(async move {
+                store
+                    .append("account-1", ExpectedVersion::Any, events)
+                    .await?;
+                let store = store
+                    .chain(snapshots, |mut snapshots| {
+                        Box::pin(async move {
+                            let mut account = snapshots.load::<Account>("account-1").await?;
+                            snapshots.save_if_due(&mut account, POLICY).await?;
+                            Ok(snapshots)
+                        })
+                    })
+                    .await?;
+                if fail {
+                    return Err(TxError::Database(sqlx::Error::RowNotFound));
+                }
+                Ok(store)
+            })
+        })
+        .await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_snapshot_chained_with_append_commits_together(pool: PgPool) -> anyhow::Result<()> {
+    let store = EventStoreRepository::new(pool.clone());
+    let snapshots = SnapshotRepository::new(pool.clone());
+
+    assert!(append_and_snapshot(&store, &snapshots, true).await.is_err());
+    let account = snapshots.clone().load::<Account>("account-1").await?;
+    assert_eq!(
+        (0, 0, 0),
+        (
+            account.state.balance,
+            account.version,
+            account.snapshot_version
+        )
+    );
+
+    append_and_snapshot(&store, &snapshots, false).await?;
+    let account = snapshots.clone().load::<Account>("account-1").await?;
+    assert_eq!(
+        (15, 3, 3),
+        (
+            account.state.balance,
+            account.version,
+            account.snapshot_version
+        )
+    );
+    Ok(())
+}
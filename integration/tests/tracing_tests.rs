@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use tx_chainable::{Begin, Chainable};
+use tx_chainable_integration::{EventsRepository, UsersRepository};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default)]
+struct CapturedSpan {
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: HashMap<String, String>,
+}
+
+/// Records every span with its fields, in creation order.
+#[derive(Clone, Default)]
+struct CaptureLayer {
+    spans: Arc<Mutex<Vec<(Id, CapturedSpan)>>>,
+}
+
+impl CaptureLayer {
+    fn spans(&self) -> Vec<CapturedSpan> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, span)| span.clone())
+            .collect()
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{value:?}"));
+    }
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut span = CapturedSpan {
+            name: attrs.metadata().name(),
+            parent: ctx.span(id).and_then(|s| s.parent()).map(|p| p.name()),
+            ..Default::default()
+        };
+        attrs.record(&mut FieldVisitor(&mut span.fields));
+        self.spans.lock().unwrap().push((id.clone(), span));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+        let mut spans = self.spans.lock().unwrap();
+        if let Some((_, span)) = spans.iter_mut().rev().find(|(i, _)| i == id) {
+            values.record(&mut FieldVisitor(&mut span.fields));
+        }
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_begin_and_chain_spans(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let layer = CaptureLayer::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(layer.clone()));
+
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+    let user_id = Uuid::new_v4();
+
+    events_repo
+        .begin(|events| {
+            Box::pin(async move {
+                let events = events
+                    .chain(&users_repo, |mut users| {
+                        Box::pin(async move {
+                            users
+                                .create_user(user_id, "Traced User".to_string())
+                                .await?;
+                            Ok(users)
+                        })
+                    })
+                    .await?;
+                Ok(events)
+            })
+        })
+        .await?;
+
+    let spans = layer.spans();
+    assert_eq!(2, spans.len());
+
+    let begin = &spans[0];
+    assert_eq!("tx.begin", begin.name);
+    assert_eq!("EventsRepository", begin.fields["repository"]);
+    assert_eq!("committed", begin.fields["outcome"]);
+    assert!(!begin.fields.contains_key("attempt"));
+    assert!(begin.fields.contains_key("duration_ms"));
+
+    let chain = &spans[1];
+    assert_eq!("tx.chain", chain.name);
+    assert_eq!(Some("tx.begin"), chain.parent);
+    assert_eq!(
+        "EventsRepository -> UsersRepository",
+        chain.fields["otel.name"]
+    );
+    assert_eq!("ok", chain.fields["outcome"]);
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_begin_span_records_rollback(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let layer = CaptureLayer::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(layer.clone()));
+
+    let events_repo = EventsRepository::new(pool.clone());
+
+    let result = events_repo
+        .begin(|_events| Box::pin(async move { Err(sqlx::Error::RowNotFound.into()) }))
+        .await;
+    assert!(result.is_err());
+
+    let spans = layer.spans();
+    assert_eq!("rolled_back", spans[0].fields["outcome"]);
+    assert!(spans[0].fields.contains_key("error"));
+
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_chain_closure_runs_in_chain_span(pool: sqlx::PgPool) -> anyhow::Result<()> {
+    let layer = CaptureLayer::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(layer.clone()));
+
+    let events_repo = EventsRepository::new(pool.clone());
+    let users_repo = UsersRepository::new(pool.clone());
+
+    events_repo
+        .begin(|events| {
+            Box::pin(async move {
+                events
+                    .chain(&users_repo, |users| {
+                        // Synchronous work before the closure's future is built
+                        let _setup = tracing::info_span!("chain.setup");
+                        Box::pin(async move { Ok(users) })
+                    })
+                    .await
+            })
+        })
+        .await?;
+
+    let spans = layer.spans();
+    let setup = spans
+        .iter()
+        .find(|span| span.name == "chain.setup")
+        .unwrap();
+    assert_eq!(Some("tx.chain"), setup.parent);
+    Ok(())
+}
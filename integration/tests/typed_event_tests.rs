@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::LazyLock;
+use tx_chainable::Begin;
+use tx_chainable_integration::{
+    DecodeEvent, EventType, EventTypeError, EventsRepository, Upcasters,
+};
+use uuid::Uuid;
+
+/// Version 1 had no currency; version 2 added it, defaulting old orders to EUR.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct OrderPlaced {
+    amount: i64,
+    currency: String,
+}
+
+static ORDER_PLACED_UPCASTERS: LazyLock<Upcasters> = LazyLock::new(|| {
+    Upcasters::new().from(1, |mut payload| {
+        payload["currency"] = "EUR".into();
+        Ok(payload)
+    })
+});
+
+impl EventType for OrderPlaced {
+    const TYPE: &'static str = "order_placed";
+    const VERSION: i32 = 2;
+
+    fn upcasters() -> &'static Upcasters {
+        &ORDER_PLACED_UPCASTERS
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct OrderCancelled {
+    reason: String,
+}
+
+impl EventType for OrderCancelled {
+    const TYPE: &'static str = "order_cancelled";
+}
+
+#[derive(Debug, PartialEq)]
+enum OrderEvent {
+    Placed(OrderPlaced),
+    Cancelled(OrderCancelled),
+}
+
+impl DecodeEvent for OrderEvent {
+    fn decode(
+        name: &str,
+        version: i32,
+        payload: serde_json::Value,
+    ) -> Result<Self, EventTypeError> {
+        match name {
+            OrderPlaced::TYPE => OrderPlaced::decode(name, version, payload).map(Self::Placed),
+            OrderCancelled::TYPE => {
+                OrderCancelled::decode(name, version, payload).map(Self::Cancelled)
+            }
+            _ => Err(EventTypeError::UnknownType {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_typed_event_round_trip(pool: PgPool) -> anyhow::Result<()> {
+    let events_repo = EventsRepository::new(pool.clone());
+    let id = Uuid::new_v4();
+    let placed = OrderPlaced {
+        amount: 42,
+        currency: "USD".to_string(),
+    };
+
+    let stored = placed.clone();
+    events_repo
+        .begin(|mut events| {
+            Box::pin(async move {
+                events.create_typed(id, &stored).await?;
+                Ok(events)
+            })
+        })
+        .await?;
+
+    let version: i32 = sqlx::query_scalar("SELECT schema_version FROM events WHERE id = $1")
+        .bind(id)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(2, version);
+    let mut reader = events_repo.clone();
+    assert_eq!(Some(placed), reader.get_typed::<OrderPlaced>(id).await?);
+    assert_eq!(None, reader.get_typed::<OrderPlaced>(Uuid::new_v4()).await?);
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_old_payloads_are_upcast(pool: PgPool) -> anyhow::Result<()> {
+    let mut events_repo = EventsRepository::new(pool);
+    let id = Uuid::new_v4();
+    // Written before the currency existed, as schema version 1
+    events_repo
+        .create_event(
+            id,
+            "order_placed".to_string(),
+            serde_json::json!({ "amount": 7 }),
+        )
+        .await?;
+
+    assert_eq!(
+        Some(OrderPlaced {
+            amount: 7,
+            currency: "EUR".to_string(),
+        }),
+        events_repo.get_typed(id).await?
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_mixed_events_decode_into_enum(pool: PgPool) -> anyhow::Result<()> {
+    let mut events_repo = EventsRepository::new(pool);
+    events_repo
+        .create_typed(
+            Uuid::new_v4(),
+            &OrderCancelled {
+                reason: "changed mind".to_string(),
+            },
+        )
+        .await?;
+    events_repo
+        .create_typed(
+            Uuid::new_v4(),
+            &OrderPlaced {
+                amount: 3,
+                currency: "EUR".to_string(),
+            },
+        )
+        .await?;
+
+    let events = events_repo.get_typed_events::<OrderEvent>(10).await?;
+    assert_eq!(
+        vec![
+            OrderEvent::Cancelled(OrderCancelled {
+                reason: "changed mind".to_string()
+            }),
+            OrderEvent::Placed(OrderPlaced {
+                amount: 3,
+                currency: "EUR".to_string()
+            }),
+        ],
+        events
+    );
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn test_undecodable_events_are_typed_errors(pool: PgPool) -> anyhow::Result<()> {
+    let mut events_repo = EventsRepository::new(pool.clone());
+    let signup = Uuid::new_v4();
+    events_repo
+        .create_event(signup, "user_signed_up".to_string(), serde_json::json!({}))
+        .await?;
+
+    let error = events_repo
+        .get_typed::<OrderPlaced>(signup)
+        .await
+        .expect_err("not an order");
+    assert!(matches!(
+        error.domain::<EventTypeError>(),
+        Some(EventTypeError::UnknownType { name }) if name == "user_signed_up"
+    ));
+    let error = events_repo
+        .get_typed_events::<OrderEvent>(10)
+        .await
+        .expect_err("signups are not order events");
+    assert!(matches!(
+        error.domain::<EventTypeError>(),
+        Some(EventTypeError::UnknownType { name }) if name == "user_signed_up"
+    ));
+
+    let future = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO events (id, name, payload, schema_version) VALUES ($1, 'order_placed', '{}', 3)",
+    )
+    .bind(future)
+    .execute(&pool)
+    .await?;
+    let error = events_repo
+        .get_typed::<OrderPlaced>(future)
+        .await
+        .expect_err("written by a newer schema");
+    assert!(matches!(
+        error.domain::<EventTypeError>(),
+        Some(EventTypeError::UnsupportedVersion { version: 3, .. })
+    ));
+    Ok(())
+}
@@ -0,0 +1,466 @@
+//! Property-based atomicity checks behind the `proptest` feature.
+//!
+//! An [`AtomicityHarness`] generates random plans over its registered
+//! [`Participant`]s: a `begin` on one of them whose closure performs random
+//! writes, `chain`s into other participants (nested up to a maximum depth)
+//! and possibly fails at a random point, optionally with a statement or
+//! COMMIT failure injected through a [`FaultSchedule`]. After each `begin` it
+//! checks the oracle: if `begin` returned `Ok`, every write of the plan is
+//! visible; if it failed, none is. The plan's writes are then removed, so
+//! that shrinking can rerun it as it was. A violating plan is shrunk to a
+//! minimal one.
+
+use crate::trace;
+use crate::{
+    Begin, BoxFuture, Chainable, DbErrorKind, Erased, FaultSchedule, Faulty, Transaction, Tx,
+    TxError, TxRepositoryOf,
+};
+use proptest::collection::vec;
+use proptest::option;
+use proptest::prelude::*;
+use proptest::strategy::{BoxedStrategy, ValueTree};
+use proptest::test_runner::{Config, RngSeed, TestRunner};
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+
+/// A repository taking part in generated plans.
+///
+/// Implementations describe the writes the repository can perform, how to
+/// perform one inside a transaction, how to tell whether it was committed and
+/// how to remove it again.
+pub trait Participant: Send + Sync + 'static {
+    type Repository: Tx + Clone + Send + Sync + 'static;
+    type Write: fmt::Debug + Clone + Send + Sync + 'static;
+
+    fn repository(&self) -> &Self::Repository;
+
+    /// Generates writes. The writes of one plan should not collide, e.g. by
+    /// carrying a random id.
+    fn writes(&self) -> BoxedStrategy<Self::Write>;
+
+    fn write<'tx>(
+        repository: TxRepositoryOf<'tx, Self::Repository>,
+        write: Self::Write,
+    ) -> BoxFuture<'tx, Result<TxRepositoryOf<'tx, Self::Repository>, TxError>>;
+
+    /// Whether `write` is visible outside any transaction.
+    fn is_visible<'a>(&'a self, write: &'a Self::Write)
+        -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+
+    /// Deletes `write`, outside any transaction, if it was committed.
+    fn remove<'a>(&'a self, write: &'a Self::Write) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+}
+
+/// Generates plans over registered participants and checks that every
+/// `begin` is all-or-nothing.
+pub struct AtomicityHarness {
+    participants: Vec<Arc<dyn DynParticipant>>,
+    config: Config,
+    max_depth: u32,
+}
+
+impl Default for AtomicityHarness {
+    fn default() -> Self {
+        Self {
+            participants: Vec::new(),
+            config: Config::with_cases(64),
+            max_depth: 2,
+        }
+    }
+}
+
+impl AtomicityHarness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn participant<P>(mut self, participant: P) -> Self
+    where
+        P: Participant,
+        for<'tx> Faulty<P::Repository>:
+            Begin<'tx, TxRepository<'tx> = <P::Repository as Tx>::TxRepository<'tx>>,
+        for<'tx> <P::Repository as Tx>::TxRepository<'tx>: Send,
+    {
+        self.participants.push(Arc::new(Erased(participant)));
+        self
+    }
+
+    /// Number of cases and shrink iterations, and the seed. Defaults to 64
+    /// cases and a random seed, which a violation reports.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// How deeply `chain` calls may nest. Defaults to 2.
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Runs the configured number of random plans, returning the minimal
+    /// plan found to violate atomicity, if any.
+    pub async fn run(&self) -> Result<(), AtomicityViolation> {
+        assert!(!self.participants.is_empty(), "no participants registered");
+        let strategy = self.plans();
+        let seed = match self.config.rng_seed {
+            RngSeed::Fixed(seed) => seed,
+            RngSeed::Random => RandomState::new().build_hasher().finish(),
+        };
+        let mut runner = TestRunner::new(Config {
+            rng_seed: RngSeed::Fixed(seed),
+            ..self.config.clone()
+        });
+        for _ in 0..self.config.cases {
+            let mut tree = strategy
+                .new_tree(&mut runner)
+                .expect("plan strategies never reject");
+            let Err(reason) = self.check(&tree.current()).await else {
+                continue;
+            };
+            let mut violation = AtomicityViolation {
+                plan: format!("{:#?}", tree.current()),
+                reason,
+                seed,
+            };
+            let mut iterations = 0;
+            let mut shrinking = tree.simplify();
+            while shrinking && iterations < self.config.max_shrink_iters {
+                iterations += 1;
+                shrinking = match self.check(&tree.current()).await {
+                    Err(reason) => {
+                        violation = AtomicityViolation {
+                            plan: format!("{:#?}", tree.current()),
+                            reason,
+                            seed,
+                        };
+                        tree.simplify()
+                    }
+                    Ok(()) => tree.complicate(),
+                };
+            }
+            return Err(violation);
+        }
+        Ok(())
+    }
+
+    fn plans(&self) -> BoxedStrategy<Plan> {
+        let participants: Arc<[Arc<dyn DynParticipant>]> = self.participants.clone().into();
+        let max_depth = self.max_depth;
+        let faults = prop_oneof![
+            (1..8usize).prop_map(InjectedFault::Statement),
+            Just(InjectedFault::Commit),
+        ];
+        let faults = option::weighted(0.25, faults);
+        (0..participants.len())
+            .prop_flat_map(move |begin| {
+                (
+                    Just(begin),
+                    Just(participants[begin].name()),
+                    steps(participants.clone(), begin, max_depth),
+                    faults.clone(),
+                )
+            })
+            .prop_map(|(begin, name, steps, fault)| Plan {
+                begin,
+                name,
+                steps,
+                fault,
+            })
+            .boxed()
+    }
+
+    async fn check(&self, plan: &Plan) -> Result<(), String> {
+        let participant = &self.participants[plan.begin];
+        let result = participant
+            .begin(&plan.steps, plan.fault, &self.participants)
+            .await;
+        let mut writes = Vec::new();
+        collect_writes(plan.begin, &plan.steps, &mut writes);
+        let mut visible = 0;
+        for (participant, write) in &writes {
+            let participant = &self.participants[*participant];
+            if participant
+                .is_visible(write)
+                .await
+                .map_err(|e| format!("checking visibility failed: {e}"))?
+            {
+                visible += 1;
+            }
+        }
+        for (participant, write) in &writes {
+            self.participants[*participant]
+                .remove(write)
+                .await
+                .map_err(|e| format!("removing a write failed: {e}"))?;
+        }
+        let total = writes.len();
+        let fails = fails(&plan.steps);
+        match result {
+            Ok(()) if fails => Err("begin returned Ok although its closure failed".to_string()),
+            Ok(()) if visible < total => Err(format!(
+                "begin returned Ok but only {visible} of {total} writes are visible"
+            )),
+            Err(e) if !fails && plan.fault.is_none() => {
+                Err(format!("begin failed without an injected failure: {e}"))
+            }
+            Err(e) if visible > 0 => Err(format!(
+                "begin failed ({e}) but {visible} of {total} writes are visible"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A plan, shrunk as far as possible, after which some writes were visible
+/// and others not, or `begin` returned the wrong result.
+#[derive(Debug)]
+pub struct AtomicityViolation {
+    pub plan: String,
+    pub reason: String,
+    /// Reproduces the run through [`Config::rng_seed`] or the
+    /// `PROPTEST_RNG_SEED` environment variable.
+    pub seed: u64,
+}
+
+impl fmt::Display for AtomicityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "atomicity violated: {}\nminimal plan: {}\nseed: {}",
+            self.reason, self.plan, self.seed
+        )
+    }
+}
+
+impl std::error::Error for AtomicityViolation {}
+
+/// Error returned by a closure at a `Fail` step.
+#[derive(Debug)]
+struct InjectedFailure;
+
+impl fmt::Display for InjectedFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("injected failure")
+    }
+}
+
+impl std::error::Error for InjectedFailure {}
+
+#[derive(Debug, Clone)]
+struct Plan {
+    begin: usize,
+    // Only shown in violations
+    #[allow(dead_code)]
+    name: &'static str,
+    steps: Vec<Step>,
+    fault: Option<InjectedFault>,
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    /// A write by the repository whose closure runs the step.
+    Write(AnyWrite),
+    Chain {
+        to: usize,
+        #[allow(dead_code)]
+        name: &'static str,
+        steps: Vec<Step>,
+    },
+    /// The closure returns an error.
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum InjectedFault {
+    Statement(usize),
+    Commit,
+}
+
+impl InjectedFault {
+    fn schedule(fault: Option<Self>) -> FaultSchedule {
+        let schedule = FaultSchedule::new();
+        match fault {
+            Some(Self::Statement(n)) => {
+                schedule.fail_statement(n, DbErrorKind::SerializationFailure)
+            }
+            Some(Self::Commit) => schedule.fail_commit(DbErrorKind::SerializationFailure),
+            None => schedule,
+        }
+    }
+}
+
+/// Steps of a closure run by participant `current`.
+fn steps(
+    participants: Arc<[Arc<dyn DynParticipant>]>,
+    current: usize,
+    depth: u32,
+) -> BoxedStrategy<Vec<Step>> {
+    let write = participants[current].writes().prop_map(Step::Write);
+    let step = if depth == 0 {
+        prop_oneof![8 => write, 1 => Just(Step::Fail)].boxed()
+    } else {
+        let chain = (0..participants.len()).prop_flat_map(move |to| {
+            let name = participants[to].name();
+            steps(participants.clone(), to, depth - 1).prop_map(move |steps| Step::Chain {
+                to,
+                name,
+                steps,
+            })
+        });
+        prop_oneof![6 => write, 1 => Just(Step::Fail), 3 => chain].boxed()
+    };
+    vec(step, 0..4).boxed()
+}
+
+fn fails(steps: &[Step]) -> bool {
+    steps.iter().any(|step| match step {
+        Step::Fail => true,
+        Step::Chain { steps, .. } => fails(steps),
+        Step::Write(_) => false,
+    })
+}
+
+/// Writes a plan attempts, up to the first failing step.
+fn collect_writes(current: usize, steps: &[Step], writes: &mut Vec<(usize, AnyWrite)>) -> bool {
+    for step in steps {
+        match step {
+            Step::Write(write) => writes.push((current, write.clone())),
+            Step::Chain { to, steps, .. } => {
+                if !collect_writes(*to, steps, writes) {
+                    return false;
+                }
+            }
+            Step::Fail => return false,
+        }
+    }
+    true
+}
+
+/// A participant's write with its type erased.
+#[derive(Clone)]
+struct AnyWrite(Arc<dyn Any + Send + Sync>, Arc<str>);
+
+impl AnyWrite {
+    fn new<W: fmt::Debug + Send + Sync + 'static>(write: W) -> Self {
+        let debug = format!("{write:?}");
+        Self(Arc::new(write), debug.into())
+    }
+
+    fn get<W: Clone + 'static>(&self) -> W {
+        self.0
+            .downcast_ref::<W>()
+            .expect("write generated by another participant")
+            .clone()
+    }
+}
+
+impl fmt::Debug for AnyWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.1)
+    }
+}
+
+trait DynParticipant: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn writes(&self) -> BoxedStrategy<AnyWrite>;
+
+    fn begin<'a>(
+        &'a self,
+        steps: &'a [Step],
+        fault: Option<InjectedFault>,
+        participants: &'a [Arc<dyn DynParticipant>],
+    ) -> BoxFuture<'a, Result<(), TxError>>;
+
+    fn chain<'tx>(
+        &'tx self,
+        tx: Transaction<'tx>,
+        steps: &'tx [Step],
+        participants: &'tx [Arc<dyn DynParticipant>],
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, TxError>>;
+
+    fn is_visible<'a>(&'a self, write: &'a AnyWrite) -> BoxFuture<'a, Result<bool, sqlx::Error>>;
+
+    fn remove<'a>(&'a self, write: &'a AnyWrite) -> BoxFuture<'a, Result<(), sqlx::Error>>;
+}
+
+impl<P> DynParticipant for Erased<P>
+where
+    P: Participant,
+    for<'tx> Faulty<P::Repository>:
+        Begin<'tx, TxRepository<'tx> = <P::Repository as Tx>::TxRepository<'tx>>,
+    for<'tx> <P::Repository as Tx>::TxRepository<'tx>: Send,
+{
+    fn name(&self) -> &'static str {
+        trace::short_name(P::Repository::name())
+    }
+
+    fn writes(&self) -> BoxedStrategy<AnyWrite> {
+        self.0.writes().prop_map(AnyWrite::new).boxed()
+    }
+
+    fn begin<'a>(
+        &'a self,
+        steps: &'a [Step],
+        fault: Option<InjectedFault>,
+        participants: &'a [Arc<dyn DynParticipant>],
+    ) -> BoxFuture<'a, Result<(), TxError>> {
+        let repository = Faulty::new(self.0.repository().clone(), InjectedFault::schedule(fault));
+        Box::pin(async move {
+            repository
+                .begin(|repo| run::<P>(repo, steps, participants))
+                .await
+        })
+    }
+
+    fn chain<'tx>(
+        &'tx self,
+        tx: Transaction<'tx>,
+        steps: &'tx [Step],
+        participants: &'tx [Arc<dyn DynParticipant>],
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, TxError>> {
+        tx.chain(self.0.repository(), |repo| {
+            run::<P>(repo, steps, participants)
+        })
+    }
+
+    fn is_visible<'a>(&'a self, write: &'a AnyWrite) -> BoxFuture<'a, Result<bool, sqlx::Error>> {
+        Box::pin(async move { self.0.is_visible(&write.get()).await })
+    }
+
+    fn remove<'a>(&'a self, write: &'a AnyWrite) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move { self.0.remove(&write.get()).await })
+    }
+}
+
+/// Runs `steps` in the closure of participant `P`.
+fn run<'tx, P>(
+    repo: <P::Repository as Tx>::TxRepository<'tx>,
+    steps: &'tx [Step],
+    participants: &'tx [Arc<dyn DynParticipant>],
+) -> BoxFuture<'tx, Result<<P::Repository as Tx>::TxRepository<'tx>, TxError>>
+where
+    P: Participant,
+    for<'a> <P::Repository as Tx>::TxRepository<'a>: Send,
+{
+    Box::pin(async move {
+        let mut repo = repo;
+        for step in steps {
+            repo = match step {
+                Step::Write(write) => P::write(repo, write.get()).await?,
+                Step::Chain { to, steps, .. } => {
+                    let tx = participants[*to]
+                        .chain(repo.into(), steps, participants)
+                        .await?;
+                    tx.into()
+                }
+                Step::Fail => return Err(TxError::Domain(Box::new(InjectedFailure))),
+            };
+        }
+        Ok(repo)
+    })
+}
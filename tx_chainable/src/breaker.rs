@@ -0,0 +1,219 @@
+use crate::{DbErrorKind, TxError};
+use sqlx::PgPool;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type StateChange = Arc<dyn Fn(CircuitState, CircuitState) + Send + Sync>;
+
+/// Fails `begin` fast while Postgres is unreachable, instead of letting every
+/// caller wait out the pool's acquire timeout.
+///
+/// Registered on a [`TxCoordinator`](crate::TxCoordinator). After
+/// `failure_threshold` consecutive connection-class failures of BEGIN the
+/// circuit opens and `begin` fails with [`TxError::CircuitOpen`]. Once
+/// `open_for` has passed, a limited number of half-open probes are let
+/// through: a success closes the circuit, a failure opens it again.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    half_open_probes: usize,
+    on_state_change: Option<StateChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold,
+            open_for: Duration::from_secs(30),
+            half_open_probes: 1,
+            on_state_change: None,
+        }
+    }
+
+    /// How long the circuit stays open before probing. Defaults to 30 seconds.
+    pub fn open_for(mut self, open_for: Duration) -> Self {
+        self.open_for = open_for;
+        self
+    }
+
+    /// How many probes may run at once while half-open. Defaults to one.
+    pub fn half_open_probes(mut self, probes: usize) -> Self {
+        self.half_open_probes = probes;
+        self
+    }
+
+    /// Called with the previous and the new state on every transition.
+    pub fn on_state_change<F>(mut self, f: F) -> Self
+    where
+        F: Fn(CircuitState, CircuitState) + Send + Sync + 'static,
+    {
+        self.on_state_change = Some(Arc::new(f));
+        self
+    }
+}
+
+impl fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("failure_threshold", &self.failure_threshold)
+            .field("open_for", &self.open_for)
+            .field("half_open_probes", &self.half_open_probes)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct BreakerState {
+    breaker: CircuitBreaker,
+    state: Mutex<Circuit>,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Instant,
+    probes: usize,
+}
+
+impl BreakerState {
+    pub(crate) fn new(breaker: CircuitBreaker) -> Self {
+        Self {
+            breaker,
+            state: Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                failures: 0,
+                opened_at: Instant::now(),
+                probes: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn state(&self) -> CircuitState {
+        self.state.lock().unwrap().state
+    }
+
+    /// Runs BEGIN, acquiring from `pool` if any, through the circuit.
+    pub(crate) async fn call<T>(
+        &self,
+        begin: impl Future<Output = Result<T, sqlx::Error>>,
+        pool: Option<&PgPool>,
+    ) -> Result<T, TxError> {
+        let probe = self.admit()?;
+        let result = begin.await;
+        let failed = matches!(&result, Err(e) if is_connection_failure(e, pool));
+        probe.finish(failed);
+        Ok(result?)
+    }
+
+    fn admit(&self) -> Result<Probe<'_>, TxError> {
+        let mut circuit = self.state.lock().unwrap();
+        let transition = match circuit.state {
+            CircuitState::Closed => None,
+            CircuitState::Open if circuit.opened_at.elapsed() >= self.breaker.open_for => {
+                Some(circuit.set(CircuitState::HalfOpen))
+            }
+            CircuitState::HalfOpen if circuit.probes < self.breaker.half_open_probes => None,
+            CircuitState::Open | CircuitState::HalfOpen => return Err(TxError::CircuitOpen),
+        };
+        let probing = circuit.state == CircuitState::HalfOpen;
+        if probing {
+            circuit.probes += 1;
+        }
+        drop(circuit);
+        self.notify(transition);
+        Ok(Probe {
+            breaker: self,
+            probing,
+            finished: false,
+        })
+    }
+
+    fn notify(&self, transition: Option<(CircuitState, CircuitState)>) {
+        if let (Some((from, to)), Some(f)) = (transition, &self.breaker.on_state_change) {
+            f(from, to);
+        }
+    }
+}
+
+impl Circuit {
+    fn set(&mut self, state: CircuitState) -> (CircuitState, CircuitState) {
+        let from = std::mem::replace(&mut self.state, state);
+        match state {
+            CircuitState::Closed => self.failures = 0,
+            CircuitState::Open => self.opened_at = Instant::now(),
+            CircuitState::HalfOpen => self.probes = 0,
+        }
+        (from, state)
+    }
+}
+
+/// A BEGIN let through the circuit. Dropped without finishing (the `begin`
+/// future was canceled) it only releases its half-open probe slot.
+struct Probe<'a> {
+    breaker: &'a BreakerState,
+    probing: bool,
+    finished: bool,
+}
+
+impl Probe<'_> {
+    fn finish(mut self, failed: bool) {
+        self.finished = true;
+        let mut circuit = self.breaker.state.lock().unwrap();
+        if self.probing {
+            circuit.probes = circuit.probes.saturating_sub(1);
+        }
+        let transition = match (circuit.state, failed) {
+            (CircuitState::Closed, true) => {
+                circuit.failures += 1;
+                (circuit.failures >= self.breaker.breaker.failure_threshold)
+                    .then(|| circuit.set(CircuitState::Open))
+            }
+            (CircuitState::Closed, false) => {
+                circuit.failures = 0;
+                None
+            }
+            (CircuitState::HalfOpen, true) => Some(circuit.set(CircuitState::Open)),
+            (CircuitState::HalfOpen, false) => Some(circuit.set(CircuitState::Closed)),
+            // a concurrent probe already decided
+            (CircuitState::Open, _) => None,
+        };
+        drop(circuit);
+        self.breaker.notify(transition);
+    }
+}
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if self.probing && !self.finished {
+            let mut circuit = self.breaker.state.lock().unwrap();
+            circuit.probes = circuit.probes.saturating_sub(1);
+        }
+    }
+}
+
+/// Failures that mean Postgres could not be reached, as opposed to BEGIN
+/// being refused by a healthy server.
+///
+/// sqlx retries refused connections until the acquire timeout, so an
+/// unreachable server mostly shows as a pool timeout. That is only one while
+/// the pool has room for new connections: a pool with every connection open
+/// timed out waiting for one to be released.
+fn is_connection_failure(error: &sqlx::Error, pool: Option<&PgPool>) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut => {
+            pool.is_some_and(|pool| pool.size() < pool.options().get_max_connections())
+        }
+        error => DbErrorKind::classify(error) == Some(DbErrorKind::ConnectionLost),
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Per-transaction limits, enforced on every statement run through
+/// `Execute::execute` on a transaction.
+#[derive(Debug, Clone, Default)]
+pub struct TxBudget {
+    max_statements: Option<usize>,
+    max_rows: Option<u64>,
+    max_duration: Option<Duration>,
+    action: BudgetAction,
+}
+
+/// What happens when a statement crosses a [`TxBudget`] limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetAction {
+    /// Log a warning through `tracing`, once per limit. Does nothing without
+    /// the `tracing` feature, like [`EscapeAction::Warn`](crate::EscapeAction::Warn).
+    Warn,
+    /// Fail the statement with [`TxError::BudgetExceeded`](crate::TxError::BudgetExceeded).
+    #[default]
+    Fail,
+}
+
+impl TxBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_statements(mut self, max: usize) -> Self {
+        self.max_statements = Some(max);
+        self
+    }
+
+    /// Rows returned by all statements together.
+    pub fn max_rows(mut self, max: u64) -> Self {
+        self.max_rows = Some(max);
+        self
+    }
+
+    /// Wall time spent holding the connection, checked whenever a statement starts.
+    pub fn max_duration(mut self, max: Duration) -> Self {
+        self.max_duration = Some(max);
+        self
+    }
+
+    pub fn action(mut self, action: BudgetAction) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetLimit {
+    Statements(usize),
+    Rows(u64),
+    Duration(Duration),
+}
+
+impl fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Statements(max) => write!(f, "{max} statements"),
+            Self::Rows(max) => write!(f, "{max} rows"),
+            Self::Duration(max) => write!(f, "{max:?} holding the connection"),
+        }
+    }
+}
+
+/// A statement crossed a [`TxBudget`] limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// The repository whose statement crossed the limit.
+    pub repository: &'static str,
+    pub limit: BudgetLimit,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} exceeded the transaction budget of {}",
+            self.repository, self.limit
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+impl From<BudgetExceeded> for sqlx::Error {
+    // sqlx has no variant for errors raised by an executor wrapper; `TxError`
+    // unwraps it again.
+    fn from(exceeded: BudgetExceeded) -> Self {
+        sqlx::Error::AnyDriverError(Box::new(exceeded))
+    }
+}
+
+/// Usage counted against a [`TxBudget`].
+#[derive(Debug)]
+pub(crate) struct BudgetTracker {
+    budget: TxBudget,
+    statements: AtomicUsize,
+    rows: AtomicU64,
+    warned: [AtomicBool; 3],
+}
+
+impl BudgetTracker {
+    pub(crate) fn new(budget: TxBudget) -> Self {
+        Self {
+            budget,
+            statements: AtomicUsize::new(0),
+            rows: AtomicU64::new(0),
+            warned: Default::default(),
+        }
+    }
+
+    /// Counts a statement that is about to run.
+    pub(crate) fn statement(
+        &self,
+        repository: &'static str,
+        connection_held: Duration,
+    ) -> Result<(), BudgetExceeded> {
+        let statements = self.statements.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(max) = self.budget.max_statements {
+            if statements > max {
+                self.exceeded(repository, BudgetLimit::Statements(max))?;
+            }
+        }
+        if let Some(max) = self.budget.max_duration {
+            if connection_held > max {
+                self.exceeded(repository, BudgetLimit::Duration(max))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts the rows returned by a statement that has run.
+    pub(crate) fn rows(&self, repository: &'static str, rows: u64) -> Result<(), BudgetExceeded> {
+        let total = self.rows.fetch_add(rows, Ordering::Relaxed) + rows;
+        match self.budget.max_rows {
+            Some(max) if total > max => self.exceeded(repository, BudgetLimit::Rows(max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn exceeded(&self, repository: &'static str, limit: BudgetLimit) -> Result<(), BudgetExceeded> {
+        let exceeded = BudgetExceeded { repository, limit };
+        match self.budget.action {
+            BudgetAction::Fail => Err(exceeded),
+            BudgetAction::Warn => {
+                let index = match limit {
+                    BudgetLimit::Statements(_) => 0,
+                    BudgetLimit::Rows(_) => 1,
+                    BudgetLimit::Duration(_) => 2,
+                };
+                if !self.warned[index].swap(true, Ordering::Relaxed) {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(repository, %limit, "{exceeded}");
+                }
+                Ok(())
+            }
+        }
+    }
+}
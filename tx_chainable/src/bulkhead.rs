@@ -0,0 +1,147 @@
+use crate::{trace, Tx, TxContext, TxError};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits how many transactions of one kind may run at once, so a burst of
+/// one workflow cannot take every connection in the pool.
+///
+/// Registered on a [`TxCoordinator`](crate::TxCoordinator). A transaction
+/// matching a bulkhead waits up to the queue timeout for a permit before
+/// BEGIN, then fails with [`TxError::Overloaded`].
+#[derive(Debug, Clone)]
+pub struct Bulkhead {
+    key: BulkheadKey,
+    max_concurrent: usize,
+    queue_timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BulkheadKey {
+    Name(Cow<'static, str>),
+    /// The repository's [`Tx::name`], its type path unless overridden, so
+    /// same-named repositories of different modules are told apart.
+    Repository(&'static str),
+}
+
+impl Bulkhead {
+    /// Applies to transactions begun with this [`TxOptions::name`](crate::TxOptions::name).
+    pub fn for_name(name: impl Into<Cow<'static, str>>, max_concurrent: usize) -> Self {
+        Self::new(BulkheadKey::Name(name.into()), max_concurrent)
+    }
+
+    /// Applies to transactions begun from repository `R`, whatever its executor.
+    pub fn for_repository<R: Tx>(max_concurrent: usize) -> Self {
+        Self::new(BulkheadKey::Repository(R::name()), max_concurrent)
+    }
+
+    fn new(key: BulkheadKey, max_concurrent: usize) -> Self {
+        Self {
+            key,
+            max_concurrent,
+            queue_timeout: Duration::ZERO,
+        }
+    }
+
+    /// How long `begin` waits for a permit. Defaults to zero: reject at once.
+    pub fn queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = queue_timeout;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.key {
+            BulkheadKey::Name(name) => name,
+            BulkheadKey::Repository(path) => trace::short_name(path),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct BulkheadState {
+    bulkhead: Bulkhead,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+impl BulkheadState {
+    pub(crate) fn new(bulkhead: Bulkhead) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(bulkhead.max_concurrent)),
+            bulkhead,
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        self.bulkhead.name()
+    }
+
+    pub(crate) fn matches(&self, context: &TxContext) -> bool {
+        match &self.bulkhead.key {
+            BulkheadKey::Name(name) => context.name() == name,
+            BulkheadKey::Repository(path) => context.type_path() == *path,
+        }
+    }
+
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub(crate) async fn acquire(&self) -> Result<OwnedSemaphorePermit, TxError> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+        let queued = Queued::new(self);
+        let permit = tokio::time::timeout(
+            self.bulkhead.queue_timeout,
+            self.permits.clone().acquire_owned(),
+        )
+        .await;
+        drop(queued);
+        match permit {
+            Ok(Ok(permit)) => Ok(permit),
+            // the semaphore is never closed, so this is always the timeout
+            _ => {
+                #[cfg(feature = "metrics")]
+                metrics::counter!("tx_chainable_bulkhead_rejections_total", "bulkhead" => self.bulkhead.name().to_string())
+                    .increment(1);
+                Err(TxError::Overloaded {
+                    bulkhead: self.bulkhead.name().to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// Counts a `begin` waiting for a permit, including one dropped mid-wait.
+struct Queued<'a>(&'a BulkheadState);
+
+impl<'a> Queued<'a> {
+    fn new(state: &'a BulkheadState) -> Self {
+        #[cfg(not(feature = "metrics"))]
+        state.queued.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            let depth = state.queued.fetch_add(1, Ordering::Relaxed) + 1;
+            metrics::gauge!("tx_chainable_bulkhead_queue_depth", "bulkhead" => state.bulkhead.name().to_string())
+                .set(depth as f64);
+        }
+        Self(state)
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        #[cfg(not(feature = "metrics"))]
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            let depth = self.0.queued.fetch_sub(1, Ordering::Relaxed) - 1;
+            metrics::gauge!("tx_chainable_bulkhead_queue_depth", "bulkhead" => self.0.bulkhead.name().to_string())
+                .set(depth as f64);
+        }
+    }
+}
@@ -0,0 +1,546 @@
+//! Record/replay of Postgres traffic behind the `cassette` feature.
+//!
+//! A [`Recorder`] hands out a pool whose single connection goes through a
+//! proxy to Postgres, and turns everything sent over it (BEGIN, each statement
+//! run through `Execute::execute` with its bound parameters, COMMIT) into a
+//! [`Cassette`] of requests and their responses, saved as JSON. A [`Player`]
+//! hands out a pool connected to a fake server answering from the cassette,
+//! so the same repository code runs again with no database.
+//!
+//! Replay is strict: once a request differs from the recorded one, whether in
+//! SQL or in a parameter value, it and every later request fail with a
+//! database error, and [`Player::finish`] reports the first difference.
+//! Recorded code must therefore be deterministic, e.g. use fixed ids.
+//!
+//! Only TCP connections without TLS are recorded.
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use sqlx::PgPool;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+const SSL_REQUEST: i32 = 80877103;
+
+/// Requests sent over a recorded connection and the responses Postgres gave.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+/// Messages up to and including a Sync or simple Query, and the responses up
+/// to the matching ReadyForQuery.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Interaction {
+    /// SQL parsed or queried by the request, for reading the cassette only.
+    #[serde(default)]
+    sql: Vec<String>,
+    request: Vec<Message>,
+    response: Vec<Message>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Message {
+    tag: char,
+    #[serde(with = "hex")]
+    body: Vec<u8>,
+}
+
+impl Cassette {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// Number of recorded request/response pairs.
+    pub fn len(&self) -> usize {
+        self.interactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.interactions.is_empty()
+    }
+}
+
+/// Records the traffic of a single-connection pool to Postgres.
+#[derive(Debug)]
+pub struct Recorder {
+    pool: PgPool,
+    log: Arc<Mutex<Log>>,
+    proxy: JoinHandle<()>,
+}
+
+#[derive(Debug, Default)]
+struct Log {
+    requests: Vec<Vec<Message>>,
+    responses: Vec<Vec<Message>>,
+}
+
+impl Recorder {
+    /// Starts a proxy to the server `options` point at and connects a pool through it.
+    pub async fn start(options: PgConnectOptions) -> io::Result<Self> {
+        if options.get_socket().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only TCP connections can be recorded",
+            ));
+        }
+        let target = (options.get_host().to_string(), options.get_port());
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+        let log = Arc::new(Mutex::new(Log::default()));
+        let proxy = tokio::spawn({
+            let log = log.clone();
+            async move {
+                while let Ok((client, _)) = listener.accept().await {
+                    // The pool has one connection, so connections never overlap
+                    let _ = proxy(client, &target, &log).await;
+                }
+            }
+        });
+        Ok(Self {
+            pool: single_connection(options.host("127.0.0.1").port(port)),
+            log,
+            proxy,
+        })
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Closes the pool and returns what was recorded.
+    pub async fn finish(self) -> Cassette {
+        self.pool.close().await;
+        self.proxy.abort();
+        let mut log = self.log.lock().unwrap();
+        let Log {
+            requests,
+            responses,
+        } = std::mem::take(&mut *log);
+        Cassette {
+            interactions: requests
+                .into_iter()
+                .zip(responses)
+                .map(|(request, response)| Interaction {
+                    sql: request.iter().filter_map(Message::sql).collect(),
+                    request,
+                    response,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Forwards one connection, logging requests and responses once the client
+/// has authenticated.
+async fn proxy(client: TcpStream, target: &(String, u16), log: &Mutex<Log>) -> io::Result<()> {
+    let (mut client_read, mut client_write) = client.into_split();
+    let server = TcpStream::connect((target.0.as_str(), target.1)).await?;
+    let (mut server_read, mut server_write) = server.into_split();
+    let startup = read_startup(&mut client_read, &mut client_write).await?;
+    server_write.write_all(&startup).await?;
+
+    let upstream = async {
+        let mut batch = Vec::new();
+        while let Some(message) = read_message(&mut client_read).await? {
+            message.write(&mut server_write).await?;
+            match message.tag {
+                // Password messages and Terminate
+                'p' | 'X' => {}
+                'S' | 'Q' => {
+                    batch.push(message);
+                    log.lock()
+                        .unwrap()
+                        .requests
+                        .push(std::mem::take(&mut batch));
+                }
+                _ => batch.push(message),
+            }
+        }
+        server_write.shutdown().await
+    };
+    let downstream = async {
+        let mut authenticated = false;
+        let mut batch = Vec::new();
+        while let Some(message) = read_message(&mut server_read).await? {
+            let ready = message.tag == 'Z';
+            if authenticated {
+                batch.push(message.clone());
+                if ready {
+                    log.lock()
+                        .unwrap()
+                        .responses
+                        .push(std::mem::take(&mut batch));
+                }
+            }
+            authenticated |= ready;
+            message.write(&mut client_write).await?;
+        }
+        client_write.shutdown().await
+    };
+    futures_util::future::try_join(upstream, downstream).await?;
+    Ok(())
+}
+
+/// Replays a [`Cassette`] to a single-connection pool.
+#[derive(Debug)]
+pub struct Player {
+    pool: PgPool,
+    replay: Arc<Mutex<Replay>>,
+    server: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct Replay {
+    interactions: Vec<Interaction>,
+    played: usize,
+    mismatch: Option<CassetteError>,
+}
+
+impl Player {
+    /// Starts a fake server answering from `cassette` and connects a pool to it.
+    pub async fn start(cassette: Cassette) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+        let replay = Arc::new(Mutex::new(Replay {
+            interactions: cassette.interactions,
+            played: 0,
+            mismatch: None,
+        }));
+        let server = tokio::spawn({
+            let replay = replay.clone();
+            async move {
+                while let Ok((client, _)) = listener.accept().await {
+                    let _ = serve(client, &replay).await;
+                }
+            }
+        });
+        let options = PgConnectOptions::new_without_pgpass()
+            .host("127.0.0.1")
+            .port(port)
+            .username("cassette")
+            .database("cassette");
+        Ok(Self {
+            pool: single_connection(options),
+            replay,
+            server,
+        })
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Closes the pool and checks that the whole cassette was replayed as recorded.
+    pub async fn finish(self) -> Result<(), CassetteError> {
+        self.pool.close().await;
+        self.server.abort();
+        let mut replay = self.replay.lock().unwrap();
+        if let Some(mismatch) = replay.mismatch.take() {
+            return Err(mismatch);
+        }
+        match replay.interactions.len() - replay.played {
+            0 => Ok(()),
+            unplayed => Err(CassetteError::Unplayed(unplayed)),
+        }
+    }
+}
+
+impl Replay {
+    fn respond(&mut self, request: Vec<Message>) -> Vec<Message> {
+        if self.mismatch.is_none() {
+            let index = self.played;
+            match self.interactions.get(index) {
+                Some(interaction) if interaction.request == request => {
+                    self.played += 1;
+                    return interaction.response.clone();
+                }
+                expected => {
+                    self.mismatch = Some(CassetteError::Mismatch {
+                        index,
+                        expected: expected
+                            .map_or("end of cassette".to_string(), |i| describe(&i.request)),
+                        actual: describe(&request),
+                    })
+                }
+            }
+        }
+        let error = self.mismatch.as_ref().map(ToString::to_string);
+        vec![Message::error(&error.unwrap_or_default()), Message::ready()]
+    }
+}
+
+/// Answers one connection from the cassette.
+async fn serve(client: TcpStream, replay: &Mutex<Replay>) -> io::Result<()> {
+    let (mut read, mut write) = client.into_split();
+    read_startup(&mut read, &mut write).await?;
+    for message in Message::startup() {
+        message.write(&mut write).await?;
+    }
+    let mut batch = Vec::new();
+    while let Some(message) = read_message(&mut read).await? {
+        match message.tag {
+            'X' => break,
+            'S' | 'Q' => {
+                batch.push(message);
+                let response = replay.lock().unwrap().respond(std::mem::take(&mut batch));
+                for message in response {
+                    message.write(&mut write).await?;
+                }
+            }
+            _ => batch.push(message),
+        }
+    }
+    Ok(())
+}
+
+fn single_connection(options: PgConnectOptions) -> PgPool {
+    PgPoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        // A ping would be an extra request, recorded or not depending on timing
+        .test_before_acquire(false)
+        .connect_lazy_with(options.ssl_mode(PgSslMode::Disable))
+}
+
+/// Reads the untyped startup message, declining TLS if the client asks for it.
+async fn read_startup(
+    read: &mut (impl AsyncRead + Unpin),
+    write: &mut (impl AsyncWrite + Unpin),
+) -> io::Result<Vec<u8>> {
+    loop {
+        let len = read.read_i32().await?;
+        let mut message = vec![0; len.max(4) as usize];
+        message[..4].copy_from_slice(&len.to_be_bytes());
+        read.read_exact(&mut message[4..]).await?;
+        if message.get(4..8) == Some(&SSL_REQUEST.to_be_bytes()) {
+            write.write_all(b"N").await?;
+            continue;
+        }
+        return Ok(message);
+    }
+}
+
+/// Reads a typed message, or `None` once the peer has closed the connection.
+async fn read_message(read: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Message>> {
+    let tag = match read.read_u8().await {
+        Ok(tag) => tag as char,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let len = read.read_i32().await?;
+    let mut body = vec![0; (len.max(4) - 4) as usize];
+    read.read_exact(&mut body).await?;
+    Ok(Some(Message { tag, body }))
+}
+
+impl Message {
+    fn new(tag: char, body: Vec<u8>) -> Self {
+        Self { tag, body }
+    }
+
+    async fn write(&self, write: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        let mut frame = Vec::with_capacity(self.body.len() + 5);
+        frame.push(self.tag as u8);
+        frame.extend_from_slice(&(self.body.len() as i32 + 4).to_be_bytes());
+        frame.extend_from_slice(&self.body);
+        write.write_all(&frame).await
+    }
+
+    /// AuthenticationOk, server parameters, BackendKeyData and ReadyForQuery.
+    fn startup() -> Vec<Self> {
+        let mut messages = vec![Self::new('R', 0i32.to_be_bytes().to_vec())];
+        for (name, value) in [
+            ("server_version", "16.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("TimeZone", "UTC"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            let mut body = Vec::new();
+            put_str(&mut body, name);
+            put_str(&mut body, value);
+            messages.push(Self::new('S', body));
+        }
+        messages.push(Self::new('K', [0u8, 0, 0, 1, 0, 0, 0, 0].to_vec()));
+        messages.push(Self::ready());
+        messages
+    }
+
+    fn ready() -> Self {
+        Self::new('Z', b"I".to_vec())
+    }
+
+    fn error(message: &str) -> Self {
+        let mut body = Vec::new();
+        for (field, value) in [
+            (b'S', "ERROR"),
+            (b'V', "ERROR"),
+            (b'C', "XX000"),
+            (b'M', message),
+        ] {
+            body.push(field);
+            put_str(&mut body, value);
+        }
+        body.push(0);
+        Self::new('E', body)
+    }
+
+    /// SQL of a Parse or simple Query message.
+    fn sql(&self) -> Option<String> {
+        let mut fields = self.body.split(|b| *b == 0);
+        let sql = match self.tag {
+            'P' => fields.nth(1),
+            'Q' => fields.next(),
+            _ => None,
+        }?;
+        Some(String::from_utf8_lossy(sql).into_owned())
+    }
+
+    /// Bound parameter values of a Bind message, rendered as text where they
+    /// look like text and as hex otherwise.
+    fn parameters(&self) -> Option<Vec<String>> {
+        let mut body = self.body.as_slice();
+        // Portal and statement names
+        for _ in 0..2 {
+            let end = body.iter().position(|b| *b == 0)?;
+            body = &body[end + 1..];
+        }
+        let formats = take_i16(&mut body)? as usize;
+        body = body.get(formats * 2..)?;
+        let count = take_i16(&mut body)?;
+        (0..count)
+            .map(|_| {
+                let len = take_i32(&mut body)?;
+                if len < 0 {
+                    return Some("NULL".to_string());
+                }
+                let (value, rest) = body.split_at_checked(len as usize)?;
+                body = rest;
+                Some(match std::str::from_utf8(value) {
+                    Ok(text) if !text.chars().any(char::is_control) => format!("{text:?}"),
+                    _ => format!("0x{}", hex::encode(value)),
+                })
+            })
+            .collect()
+    }
+}
+
+fn put_str(body: &mut Vec<u8>, value: &str) {
+    body.extend_from_slice(value.as_bytes());
+    body.push(0);
+}
+
+fn take_i16(body: &mut &[u8]) -> Option<i16> {
+    let (value, rest) = body.split_first_chunk()?;
+    *body = rest;
+    Some(i16::from_be_bytes(*value))
+}
+
+fn take_i32(body: &mut &[u8]) -> Option<i32> {
+    let (value, rest) = body.split_first_chunk()?;
+    *body = rest;
+    Some(i32::from_be_bytes(*value))
+}
+
+/// Summarizes a request for a mismatch report.
+fn describe(request: &[Message]) -> String {
+    request
+        .iter()
+        .filter_map(|message| match message.tag {
+            'P' | 'Q' => message.sql(),
+            'B' => message
+                .parameters()
+                .map(|parameters| format!("with [{}]", parameters.join(", "))),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Why a [`Player`] did not replay its cassette as recorded.
+#[derive(Debug)]
+pub enum CassetteError {
+    /// Request `index` differed from the recorded one.
+    Mismatch {
+        index: usize,
+        expected: String,
+        actual: String,
+    },
+    /// Recorded requests that were never made.
+    Unplayed(usize),
+}
+
+impl fmt::Display for CassetteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "cassette request {index} differs: expected `{expected}`, got `{actual}`"
+            ),
+            Self::Unplayed(count) => write!(f, "{count} recorded requests were never made"),
+        }
+    }
+}
+
+impl std::error::Error for CassetteError {}
+
+/// Hex encoding of message bodies in cassette files.
+mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Write;
+
+    pub(super) fn encode(bytes: &[u8]) -> String {
+        bytes.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    }
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 {
+            return Err(D::Error::custom("odd number of hex digits"));
+        }
+        hex.as_bytes()
+            .chunks(2)
+            .map(|pair| match (digit(pair[0]), digit(pair[1])) {
+                (Some(high), Some(low)) => Ok(high << 4 | low),
+                _ => Err(D::Error::custom(format!(
+                    "invalid hex digits {:?}",
+                    String::from_utf8_lossy(pair)
+                ))),
+            })
+            .collect()
+    }
+
+    fn digit(byte: u8) -> Option<u8> {
+        char::from(byte).to_digit(16).map(|digit| digit as u8)
+    }
+}
@@ -0,0 +1,208 @@
+use crate::breaker::{BreakerState, CircuitBreaker, CircuitState};
+use crate::bulkhead::{Bulkhead, BulkheadState};
+use crate::{TxContext, TxError};
+use futures_util::future::{self, Either};
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{watch, OwnedSemaphorePermit};
+
+/// Admission control for `begin`, shared by the repositories that return it
+/// from [`GetExecutor::coordinator`](crate::GetExecutor::coordinator).
+#[derive(Debug, Clone, Default)]
+pub struct TxCoordinator {
+    inner: Arc<Inner>,
+}
+
+/// Shared by every clone, including the bulkheads and breaker added to any
+/// of them.
+#[derive(Debug)]
+struct Inner {
+    bulkheads: Mutex<Vec<Arc<BulkheadState>>>,
+    breaker: Mutex<Option<Arc<BreakerState>>>,
+    shutting_down: Mutex<bool>,
+    in_flight: watch::Sender<usize>,
+    abort: watch::Sender<bool>,
+    aborted: AtomicUsize,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            bulkheads: Mutex::new(Vec::new()),
+            breaker: Mutex::new(None),
+            shutting_down: Mutex::new(false),
+            in_flight: watch::Sender::new(0),
+            abort: watch::Sender::new(false),
+            aborted: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// Outcome of [`TxCoordinator::shutdown`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Transactions that finished within the grace period.
+    pub drained: usize,
+    /// Transactions rolled back at the deadline.
+    pub aborted: usize,
+}
+
+impl TxCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a [`Bulkhead`]. A transaction matching several bulkheads needs a
+    /// permit from each, taken in the order they were added.
+    pub fn bulkhead(self, bulkhead: Bulkhead) -> Self {
+        self.inner
+            .bulkheads
+            .lock()
+            .unwrap()
+            .push(Arc::new(BulkheadState::new(bulkhead)));
+        self
+    }
+
+    /// Number of `begin` calls waiting for a permit of the named bulkhead.
+    pub fn queue_depth(&self, bulkhead: &str) -> Option<usize> {
+        self.inner
+            .bulkheads
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|state| state.name() == bulkhead)
+            .map(|state| state.queue_depth())
+    }
+
+    /// Puts a [`CircuitBreaker`] in front of BEGIN, replacing any previous one.
+    pub fn circuit_breaker(self, breaker: CircuitBreaker) -> Self {
+        *self.inner.breaker.lock().unwrap() = Some(Arc::new(BreakerState::new(breaker)));
+        self
+    }
+
+    /// `None` without a circuit breaker.
+    pub fn circuit_state(&self) -> Option<CircuitState> {
+        self.breaker().map(|breaker| breaker.state())
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.inner.shutting_down.lock().unwrap()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        *self.inner.in_flight.borrow()
+    }
+
+    /// Rejects new transactions with [`TxError::ShuttingDown`] and waits up
+    /// to `grace` for the open ones to finish. Transactions still open at the
+    /// deadline are rolled back and their `begin` fails with
+    /// [`TxError::ShuttingDown`]. Rollbacks still running after another
+    /// `grace` are left to finish on their own and not reported.
+    pub async fn shutdown(&self, grace: Duration) -> ShutdownReport {
+        let open = {
+            let mut shutting_down = self.inner.shutting_down.lock().unwrap();
+            *shutting_down = true;
+            self.in_flight()
+        };
+        // The counter spans every call; only this one's aborts are reported
+        let aborted_before = self.inner.aborted.load(Ordering::Relaxed);
+        let mut in_flight = self.inner.in_flight.subscribe();
+        let timed_out = tokio::time::timeout(grace, in_flight.wait_for(|n| *n == 0))
+            .await
+            .is_err();
+        if timed_out {
+            self.inner.abort.send_replace(true);
+            let _ = tokio::time::timeout(grace, in_flight.wait_for(|n| *n == 0)).await;
+        }
+        let aborted = self.inner.aborted.load(Ordering::Relaxed) - aborted_before;
+        ShutdownReport {
+            drained: open
+                .saturating_sub(aborted)
+                .saturating_sub(self.in_flight()),
+            aborted,
+        }
+    }
+
+    fn breaker(&self) -> Option<Arc<BreakerState>> {
+        self.inner.breaker.lock().unwrap().clone()
+    }
+
+    /// Waits for the bulkheads matching `context`, then counts the
+    /// transaction as in flight unless shutdown has started.
+    pub(crate) async fn admit(&self, context: &TxContext) -> Result<Admission, TxError> {
+        let bulkheads: Vec<_> = self
+            .inner
+            .bulkheads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|state| state.matches(context))
+            .cloned()
+            .collect();
+        let mut permits = Vec::new();
+        for state in bulkheads {
+            permits.push(state.acquire().await?);
+        }
+        let shutting_down = self.inner.shutting_down.lock().unwrap();
+        if *shutting_down {
+            return Err(TxError::ShuttingDown);
+        }
+        self.inner.in_flight.send_modify(|n| *n += 1);
+        Ok(Admission {
+            inner: self.inner.clone(),
+            _permits: permits,
+        })
+    }
+
+    /// Runs BEGIN through the circuit breaker, if any.
+    pub(crate) async fn begin<T>(
+        &self,
+        begin: impl Future<Output = Result<T, sqlx::Error>>,
+        pool: Option<&PgPool>,
+    ) -> Result<T, TxError> {
+        match self.breaker() {
+            Some(breaker) => breaker.call(begin, pool).await,
+            None => Ok(begin.await?),
+        }
+    }
+}
+
+/// An admitted transaction, counted as in flight and holding its bulkhead
+/// permits until dropped.
+pub(crate) struct Admission {
+    inner: Arc<Inner>,
+    _permits: Vec<OwnedSemaphorePermit>,
+}
+
+impl Admission {
+    /// Runs `fut` unless shutdown reaches its deadline first, in which case
+    /// `fut` is dropped and the transaction it left behind rolled back by
+    /// `rollback` before it counts as aborted.
+    pub(crate) async fn run<T>(
+        &self,
+        fut: impl Future<Output = Result<T, TxError>>,
+        rollback: impl Future<Output = ()>,
+    ) -> Result<T, TxError> {
+        let mut abort = self.inner.abort.subscribe();
+        {
+            let aborted = abort.wait_for(|abort| *abort);
+            tokio::pin!(fut);
+            tokio::pin!(aborted);
+            if let Either::Left((result, _)) = future::select(fut, aborted).await {
+                return result;
+            }
+        }
+        rollback.await;
+        self.inner.aborted.fetch_add(1, Ordering::Relaxed);
+        Err(TxError::ShuttingDown)
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.inner.in_flight.send_modify(|n| *n -= 1);
+    }
+}
@@ -0,0 +1,221 @@
+//! Throwaway Postgres servers for test suites, behind the `embedded` feature.
+//!
+//! [`EmbeddedPostgres::start`] runs `initdb` and `pg_ctl` from the installed
+//! Postgres binaries in a temporary directory, on a free port of localhost,
+//! and removes everything when dropped. A watchdog process also stops the
+//! server once the test process exits, however it exits, so a test that
+//! panics or is killed does not leave it running.
+//!
+//! ```ignore
+//! let server = EmbeddedPostgres::start()?;
+//! server.migrate(&sqlx::migrate!("./migrations")).await?;
+//! let pool = PgPool::connect_with(server.connect_options()).await?;
+//! ```
+
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::PgPool;
+use std::io;
+use std::net::TcpListener;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command, Stdio};
+
+const USER: &str = "postgres";
+
+/// A Postgres server running from a temporary directory.
+#[derive(Debug)]
+pub struct EmbeddedPostgres {
+    dir: PathBuf,
+    pg_ctl: PathBuf,
+    port: u16,
+    /// The account the server runs as when this process is root.
+    account: Option<(u32, u32)>,
+    /// Closing it, by dropping or exiting, tells the watchdog to stop the server.
+    watchdog: Option<ChildStdin>,
+}
+
+impl EmbeddedPostgres {
+    /// Initializes a cluster and starts it. The binaries are looked up in
+    /// `PG_BIN`, then `PATH`, then `pg_config --bindir` and the Debian
+    /// layout under `/usr/lib/postgresql`.
+    ///
+    /// Postgres refuses to run as root, so as root the server runs as the
+    /// `postgres` system account, and starting fails if there is none.
+    pub fn start() -> io::Result<Self> {
+        let initdb = find_binary("initdb")?;
+        let pg_ctl = find_binary("pg_ctl")?;
+        let port = TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port();
+        let dir = std::env::temp_dir().join(format!(
+            "tx-chainable-postgres-{}-{port}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir)?;
+        // The directory is owned by the effective user of this process
+        let account = if std::fs::metadata(&dir)?.uid() == 0 {
+            let account = system_account(USER)?;
+            std::os::unix::fs::chown(&dir, Some(account.0), Some(account.1))?;
+            Some(account)
+        } else {
+            None
+        };
+        let mut server = Self {
+            dir,
+            pg_ctl,
+            port,
+            account,
+            watchdog: None,
+        };
+
+        run(server
+            .command(initdb)
+            .arg("--pgdata")
+            .arg(server.data())
+            .args(["--username", USER, "--auth", "trust", "--encoding", "UTF8"])
+            .arg("--no-sync"))?;
+        server.spawn_watchdog()?;
+        let options = format!(
+            "-p {port} -k {} -c listen_addresses=localhost -c fsync=off",
+            server.dir.display()
+        );
+        run(server
+            .command(&server.pg_ctl)
+            .arg("--pgdata")
+            .arg(server.data())
+            .arg("--log")
+            .arg(server.dir.join("postgres.log"))
+            .args(["--options", &options, "--wait", "start"]))?;
+        Ok(server)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn url(&self) -> String {
+        format!("postgres://{USER}@localhost:{}/postgres", self.port)
+    }
+
+    pub fn connect_options(&self) -> PgConnectOptions {
+        PgConnectOptions::new()
+            .host("localhost")
+            .port(self.port)
+            .username(USER)
+            .database("postgres")
+    }
+
+    /// Applies `migrator` to the `postgres` database.
+    pub async fn migrate(&self, migrator: &Migrator) -> Result<(), sqlx::Error> {
+        let pool = PgPool::connect_with(self.connect_options()).await?;
+        let result = migrator.run(&pool).await;
+        pool.close().await;
+        Ok(result?)
+    }
+
+    fn data(&self) -> PathBuf {
+        self.dir.join("data")
+    }
+
+    /// A command run as the account owning the server.
+    fn command(&self, program: impl AsRef<std::ffi::OsStr>) -> Command {
+        let mut command = Command::new(program);
+        if let Some((uid, gid)) = self.account {
+            command.uid(uid).gid(gid);
+        }
+        command
+    }
+
+    /// Stops the server and removes its directory once this process is gone,
+    /// which the watchdog sees as the end of its stdin.
+    fn spawn_watchdog(&mut self) -> io::Result<()> {
+        let script = "cat >/dev/null; \
+             \"$0\" --pgdata \"$1/data\" --mode immediate stop; rm -rf \"$1\"";
+        let mut watchdog = self
+            .command("sh")
+            .args(["-c", script])
+            .arg(&self.pg_ctl)
+            .arg(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        self.watchdog = watchdog.stdin.take();
+        Ok(())
+    }
+}
+
+impl Drop for EmbeddedPostgres {
+    fn drop(&mut self) {
+        let _ = self
+            .command(&self.pg_ctl)
+            .arg("--pgdata")
+            .arg(self.data())
+            .args(["--mode", "immediate", "stop"])
+            .output();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The uid and gid of `name` in `/etc/passwd`.
+fn system_account(name: &str) -> io::Result<(u32, u32)> {
+    let passwd = std::fs::read_to_string("/etc/passwd")?;
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 3 && fields[0] == name)
+        .and_then(|fields| Some((fields[2].parse().ok()?, fields[3].parse().ok()?)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "Postgres refuses to run as root and there is no {name} account to run it as"
+                ),
+            )
+        })
+}
+
+fn find_binary(name: &str) -> io::Result<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    dirs.extend(std::env::var_os("PG_BIN").map(PathBuf::from));
+    if let Some(path) = std::env::var_os("PATH") {
+        dirs.extend(std::env::split_paths(&path));
+    }
+    if let Ok(output) = Command::new("pg_config").arg("--bindir").output() {
+        dirs.push(PathBuf::from(
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+    if let Ok(versions) = std::fs::read_dir("/usr/lib/postgresql") {
+        let mut versions: Vec<_> = versions.flatten().map(|entry| entry.path()).collect();
+        // Newest version first
+        versions.sort_by(|a, b| b.cmp(a));
+        dirs.extend(versions.into_iter().map(|version| version.join("bin")));
+    }
+    dirs.into_iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{name} not found; set PG_BIN to the Postgres bin directory"),
+            )
+        })
+}
+
+/// Runs a setup command, turning a failure into an error carrying its output.
+fn run(command: &mut Command) -> io::Result<()> {
+    let output = command.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let program = Path::new(command.get_program())
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    Err(io::Error::other(format!(
+        "{program} failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
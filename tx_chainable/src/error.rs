@@ -0,0 +1,227 @@
+use crate::BudgetExceeded;
+use std::fmt;
+
+pub type BoxDynError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Database failures that callers commonly need to branch on, classified from
+/// the SQLSTATE carried by a [`sqlx::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbErrorKind {
+    UniqueViolation { constraint: Option<String> },
+    ForeignKeyViolation { constraint: Option<String> },
+    CheckViolation { constraint: Option<String> },
+    SerializationFailure,
+    Deadlock,
+    LockTimeout,
+    QueryCanceled,
+    ConnectionLost,
+}
+
+impl DbErrorKind {
+    /// Returns `None` for errors that do not fall into one of the known kinds.
+    pub fn classify(error: &sqlx::Error) -> Option<Self> {
+        match error {
+            sqlx::Error::Database(db) => {
+                let constraint = db.constraint().map(str::to_string);
+                match db.code().as_deref()? {
+                    "23505" => Some(Self::UniqueViolation { constraint }),
+                    "23503" => Some(Self::ForeignKeyViolation { constraint }),
+                    "23514" => Some(Self::CheckViolation { constraint }),
+                    "40001" => Some(Self::SerializationFailure),
+                    "40P01" => Some(Self::Deadlock),
+                    "55P03" => Some(Self::LockTimeout),
+                    "57014" => Some(Self::QueryCanceled),
+                    // admin_shutdown, crash_shutdown, cannot_connect_now and
+                    // the whole connection_exception class
+                    "57P01" | "57P02" | "57P03" => Some(Self::ConnectionLost),
+                    code if code.starts_with("08") => Some(Self::ConnectionLost),
+                    _ => None,
+                }
+            }
+            sqlx::Error::Io(_) | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => {
+                Some(Self::ConnectionLost)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn constraint(&self) -> Option<&str> {
+        match self {
+            Self::UniqueViolation { constraint }
+            | Self::ForeignKeyViolation { constraint }
+            | Self::CheckViolation { constraint } => constraint.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DbErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::UniqueViolation { .. } => "unique violation",
+            Self::ForeignKeyViolation { .. } => "foreign key violation",
+            Self::CheckViolation { .. } => "check violation",
+            Self::SerializationFailure => "serialization failure",
+            Self::Deadlock => "deadlock",
+            Self::LockTimeout => "lock timeout",
+            Self::QueryCanceled => "query canceled",
+            Self::ConnectionLost => "connection lost",
+        };
+        match self.constraint() {
+            Some(constraint) => write!(f, "{name} on {constraint}"),
+            None => f.write_str(name),
+        }
+    }
+}
+
+/// Error returned by `begin`, `chain` and the closures passed to them.
+///
+/// Repository methods keep returning [`sqlx::Error`]; `?` converts it into
+/// [`TxError::Database`] inside a closure.
+#[derive(Debug)]
+pub enum TxError {
+    Database(sqlx::Error),
+    /// A domain error, produced by a [`ConstraintRegistry`] mapping.
+    Domain(BoxDynError),
+    /// A statement crossed the [`TxBudget`](crate::TxBudget) of the transaction.
+    BudgetExceeded(BudgetExceeded),
+    /// The [`TxCoordinator`](crate::TxCoordinator) is shutting down: the
+    /// transaction was either not started or rolled back at the deadline.
+    ShuttingDown,
+    /// No [`Bulkhead`](crate::Bulkhead) permit became free within its queue timeout.
+    Overloaded {
+        bulkhead: String,
+    },
+    /// The [`CircuitBreaker`](crate::CircuitBreaker) is open and BEGIN was not attempted.
+    CircuitOpen,
+    /// `operation` ran outside the transaction while its closure ran, with
+    /// [`EscapeAction::Fail`](crate::EscapeAction::Fail). The transaction
+    /// was rolled back; the escaped operation was not.
+    Escaped {
+        operation: &'static str,
+    },
+}
+
+impl TxError {
+    pub fn kind(&self) -> Option<DbErrorKind> {
+        match self {
+            Self::Database(error) => DbErrorKind::classify(error),
+            Self::Domain(_)
+            | Self::BudgetExceeded(_)
+            | Self::ShuttingDown
+            | Self::Overloaded { .. }
+            | Self::CircuitOpen
+            | Self::Escaped { .. } => None,
+        }
+    }
+
+    pub fn domain<E>(&self) -> Option<&E>
+    where
+        E: std::error::Error + 'static,
+    {
+        match self {
+            Self::Domain(error) => error.downcast_ref(),
+            Self::Database(_)
+            | Self::BudgetExceeded(_)
+            | Self::ShuttingDown
+            | Self::Overloaded { .. }
+            | Self::CircuitOpen
+            | Self::Escaped { .. } => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for TxError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::AnyDriverError(inner) => match inner.downcast::<BudgetExceeded>() {
+                Ok(exceeded) => Self::BudgetExceeded(*exceeded),
+                Err(inner) => Self::Database(sqlx::Error::AnyDriverError(inner)),
+            },
+            error => Self::Database(error),
+        }
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(error) => write!(f, "database error: {error}"),
+            Self::Domain(error) => write!(f, "{error}"),
+            Self::BudgetExceeded(exceeded) => write!(f, "{exceeded}"),
+            Self::ShuttingDown => f.write_str("transaction coordinator is shutting down"),
+            Self::Overloaded { bulkhead } => write!(f, "bulkhead {bulkhead} is full"),
+            Self::CircuitOpen => f.write_str("circuit breaker is open"),
+            Self::Escaped { operation } => write!(
+                f,
+                "{operation} ran outside the transaction, which was rolled back"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(error) => Some(error),
+            Self::Domain(error) => Some(error.as_ref()),
+            Self::BudgetExceeded(exceeded) => Some(exceeded),
+            Self::ShuttingDown
+            | Self::Overloaded { .. }
+            | Self::CircuitOpen
+            | Self::Escaped { .. } => None,
+        }
+    }
+}
+
+type ConstraintMapper = Box<dyn Fn(&DbErrorKind) -> BoxDynError + Send + Sync>;
+
+/// Maps constraint names such as `users_pkey` to domain errors.
+///
+/// A repository exposes its registry through [`Tx::constraints`](crate::Tx::constraints);
+/// `begin` and `chain` apply it to every error leaving a closure of that repository.
+#[derive(Default)]
+pub struct ConstraintRegistry {
+    mappings: Vec<(&'static str, ConstraintMapper)>,
+}
+
+impl ConstraintRegistry {
+    pub const fn new() -> Self {
+        Self {
+            mappings: Vec::new(),
+        }
+    }
+
+    pub fn map<F, E>(mut self, constraint: &'static str, f: F) -> Self
+    where
+        F: Fn(&DbErrorKind) -> E + Send + Sync + 'static,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.mappings
+            .push((constraint, Box::new(move |kind| Box::new(f(kind)))));
+        self
+    }
+
+    /// Replaces a database error with the mapped domain error, if any.
+    pub fn apply(&self, error: TxError) -> TxError {
+        let Some(kind) = error.kind() else {
+            return error;
+        };
+        let Some(constraint) = kind.constraint() else {
+            return error;
+        };
+        self.mappings
+            .iter()
+            .find(|(name, _)| *name == constraint)
+            .map(|(_, f)| TxError::Domain(f(&kind)))
+            .unwrap_or(error)
+    }
+}
+
+impl fmt::Debug for ConstraintRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.mappings.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
@@ -0,0 +1,121 @@
+use crate::TxContext;
+use std::future::Future;
+#[cfg(debug_assertions)]
+use std::sync::{Arc, OnceLock};
+
+#[cfg(debug_assertions)]
+tokio::task_local! {
+    static ACTIVE: Active;
+}
+
+#[cfg(debug_assertions)]
+#[derive(Clone)]
+struct Active {
+    #[cfg(feature = "tracing")]
+    name: String,
+    #[cfg(feature = "tracing")]
+    repository: &'static str,
+    action: EscapeAction,
+    /// The first operation that escaped, with [`EscapeAction::Fail`].
+    escaped: Arc<OnceLock<&'static str>>,
+}
+
+/// What happens when a statement bypasses the transaction open on its task,
+/// set per transaction with [`TxOptions::on_escape`](crate::TxOptions::on_escape).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EscapeAction {
+    Ignore,
+    /// Log a warning through `tracing`. Does nothing without the `tracing`
+    /// feature.
+    #[default]
+    Warn,
+    /// Roll the transaction back once its closure returns, failing `begin`
+    /// with [`TxError::Escaped`](crate::TxError::Escaped). The escaping
+    /// statement itself has already run by then.
+    Fail,
+}
+
+/// Debug-build detection of statements that escape the active transaction.
+///
+/// While a `begin` closure runs, its task is marked as inside a transaction.
+/// Running a statement through `Execute for PgPool`, or beginning another
+/// transaction, on that task then goes to a different connection: it is not
+/// rolled back with the transaction, and with a small pool it can wait forever
+/// for a connection the task itself holds. Such calls are reported as the
+/// transaction's [`EscapeAction`] says, with a warning by default.
+///
+/// Only the task running the closure is marked, not tasks it spawns. Release
+/// builds do not check.
+pub struct EscapeDetector;
+
+impl EscapeDetector {
+    /// Whether the current task is running a `begin` closure. Always `false`
+    /// in release builds.
+    pub fn in_transaction() -> bool {
+        #[cfg(debug_assertions)]
+        return ACTIVE.try_with(|_| ()).is_ok();
+        #[cfg(not(debug_assertions))]
+        false
+    }
+
+    /// Marks the current task as inside the transaction of `context` while `fut` runs.
+    pub(crate) async fn scope<F: Future>(context: &TxContext, fut: F) -> F::Output {
+        #[cfg(debug_assertions)]
+        {
+            let active = Active {
+                #[cfg(feature = "tracing")]
+                name: context.name().to_string(),
+                #[cfg(feature = "tracing")]
+                repository: context.repository(),
+                action: context.escape_action(),
+                escaped: Arc::default(),
+            };
+            ACTIVE.scope(active, fut).await
+        }
+        #[cfg(not(debug_assertions))]
+        {
+            let _ = context;
+            fut.await
+        }
+    }
+
+    /// The first operation that escaped the transaction open on this task,
+    /// if its action is [`EscapeAction::Fail`].
+    pub(crate) fn escaped() -> Option<&'static str> {
+        #[cfg(debug_assertions)]
+        return ACTIVE
+            .try_with(|active| active.escaped.get().copied())
+            .ok()
+            .flatten();
+        #[cfg(not(debug_assertions))]
+        None
+    }
+
+    /// Reports `operation` if it runs outside the transaction open on this task.
+    pub(crate) fn check(operation: &'static str) {
+        #[cfg(debug_assertions)]
+        {
+            let Ok(active) = ACTIVE.try_with(Active::clone) else {
+                return;
+            };
+            match active.action {
+                EscapeAction::Ignore => {}
+                #[cfg(feature = "tracing")]
+                EscapeAction::Warn => tracing::warn!(
+                    transaction = %active.name,
+                    "{operation} inside transaction {} (begun by {}) runs outside it and is not \
+                     rolled back; use the repository passed to the closure",
+                    active.name,
+                    active.repository
+                ),
+                #[cfg(not(feature = "tracing"))]
+                EscapeAction::Warn => {}
+                EscapeAction::Fail => {
+                    let _ = active.escaped.set(operation);
+                }
+            }
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = operation;
+    }
+}
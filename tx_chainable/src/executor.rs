@@ -0,0 +1,363 @@
+use crate::{BoxDynError, StatementReport, TxContext};
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+use futures_util::{FutureExt, StreamExt};
+use sqlx::postgres::{PgArguments, PgQueryResult, PgRow, PgStatement, PgTypeInfo};
+use sqlx::query::Query;
+use sqlx::{Acquire, Arguments, Describe, Either, Execute, Executor, PgConnection, Postgres, Row};
+use std::time::Instant;
+
+/// Executor handed to `Execute::execute` closures of transactional repositories.
+///
+/// Statements run on the transaction's connection, rewritten and recorded
+/// according to the [`TxContext`] (for example tagged with a sqlcommenter comment).
+/// In an in-memory transaction there is no connection and every statement fails.
+#[derive(Debug)]
+pub struct TxConnection<'c> {
+    conn: Option<&'c mut PgConnection>,
+    context: &'c TxContext,
+}
+
+impl<'c> TxConnection<'c> {
+    pub(crate) fn new(conn: Option<&'c mut PgConnection>, context: &'c TxContext) -> Self {
+        Self { conn, context }
+    }
+
+    pub fn context(&self) -> &TxContext {
+        self.context
+    }
+}
+
+fn no_connection() -> sqlx::Error {
+    sqlx::Error::Configuration("Postgres repository used in an in-memory transaction".into())
+}
+
+/// Appends `comment` to `sql`, keeping a trailing semicolon last.
+fn with_comment(sql: &str, comment: &str) -> String {
+    let sql = sql.trim_end();
+    match sql.strip_suffix(';') {
+        Some(sql) => format!("{sql} {comment};"),
+        None => format!("{sql} {comment}"),
+    }
+}
+
+/// Whether `sql` inserts, updates or deletes rows, judged by its leading
+/// keyword. Postgres reports the rows a SELECT returned as its row count, and
+/// those are not affected.
+fn writes(sql: &str) -> bool {
+    const WRITES: [&str; 4] = ["INSERT", "UPDATE", "DELETE", "MERGE"];
+    let mut words = sql
+        .lines()
+        .map(|line| line.split("--").next().unwrap_or_default())
+        .flat_map(|line| line.split(|c: char| !c.is_ascii_alphanumeric() && c != '_'))
+        .filter(|word| !word.is_empty());
+    match words.next() {
+        Some(word) if word.eq_ignore_ascii_case("WITH") => {
+            words.any(|word| WRITES.iter().any(|w| word.eq_ignore_ascii_case(w)))
+        }
+        Some(word) => WRITES.iter().any(|w| word.eq_ignore_ascii_case(w)),
+        None => false,
+    }
+}
+
+/// Bound values as Postgres renders them in text, read by selecting a copy of
+/// the statement's arguments.
+async fn parameter_values(
+    conn: &mut PgConnection,
+    arguments: PgArguments,
+) -> Result<Vec<Option<String>>, sqlx::Error> {
+    let count = arguments.len();
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let columns: Vec<_> = (1..=count).map(|i| format!("${i}::text")).collect();
+    let sql = format!("SELECT {}", columns.join(", "));
+    let row = sqlx::query_with(&sql, arguments)
+        .persistent(false)
+        .fetch_one(conn)
+        .await?;
+    (0..count).map(|i| row.try_get(i)).collect()
+}
+
+/// A statement taken apart so it can be rewritten and recorded.
+struct Intercepted<'q> {
+    original: &'q str,
+    sql: String,
+    arguments: Result<Option<PgArguments>, BoxDynError>,
+    persistent: bool,
+    report_values: bool,
+}
+
+impl<'q> Intercepted<'q> {
+    fn new<E>(context: &TxContext, mut query: E) -> Self
+    where
+        E: Execute<'q, Postgres>,
+    {
+        let original = query.sql();
+        let comment = context.sql_comment();
+        Self {
+            original,
+            sql: match &comment {
+                Some(comment) => with_comment(original, comment),
+                None => original.to_string(),
+            },
+            arguments: query.take_arguments(),
+            // Tagged SQL changes with the chain path, so it would only churn the cache
+            persistent: query.persistent() && comment.is_none(),
+            report_values: context
+                .recording()
+                .is_some_and(|recording| recording.parameter_values()),
+        }
+    }
+
+    /// The rewritten query, ready to be recorded. Reads the parameter values
+    /// first if they are reported.
+    async fn query<'c>(
+        &mut self,
+        context: &'c TxContext,
+        conn: &mut PgConnection,
+    ) -> Result<(Query<'_, Postgres, PgArguments>, Recorded<'c, 'q>), sqlx::Error> {
+        let arguments =
+            std::mem::replace(&mut self.arguments, Ok(None)).map_err(sqlx::Error::Encode)?;
+        let parameters = arguments.as_ref().map_or(0, Arguments::len);
+        let values = match self.report_values {
+            true => {
+                let copy = arguments.clone().unwrap_or_default();
+                Some(parameter_values(conn, copy).await?)
+            }
+            false => None,
+        };
+        let mut recorded = Recorded::new(self.original, context, parameters);
+        recorded.parameter_values = values;
+        let query = match arguments {
+            Some(arguments) => sqlx::query_with(&self.sql, arguments),
+            None => sqlx::query(&self.sql),
+        };
+        Ok((query.persistent(self.persistent), recorded))
+    }
+}
+
+/// A statement's rows, recorded in the report when it finishes or its stream
+/// is dropped.
+struct Recorded<'c, 'q> {
+    sql: &'q str,
+    context: &'c TxContext,
+    parameters: usize,
+    parameter_values: Option<Vec<Option<String>>>,
+    start: Instant,
+    rows_affected: u64,
+    rows_returned: u64,
+}
+
+impl<'c, 'q> Recorded<'c, 'q> {
+    fn new(sql: &'q str, context: &'c TxContext, parameters: usize) -> Self {
+        Self {
+            sql,
+            context,
+            parameters,
+            parameter_values: None,
+            start: Instant::now(),
+            rows_affected: 0,
+            rows_returned: 0,
+        }
+    }
+}
+
+impl Drop for Recorded<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(recording) = self.context.recording() {
+            recording.statement(StatementReport {
+                repository: self.context.repository(),
+                sql: self.sql.to_string(),
+                parameters: self.parameters,
+                parameter_values: self.parameter_values.take(),
+                rows_affected: self.rows_affected,
+                rows_returned: self.rows_returned,
+                duration: self.start.elapsed(),
+            });
+        }
+    }
+}
+
+impl<'c> Executor<'c> for TxConnection<'c> {
+    type Database = Postgres;
+
+    fn fetch_many<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxStream<'e, Result<Either<PgQueryResult, PgRow>, sqlx::Error>>
+    where
+        'c: 'e,
+        E: 'q + Execute<'q, Postgres>,
+    {
+        let Self { conn, context } = self;
+        let Some(conn) = conn else {
+            return futures_util::stream::once(async { Err(no_connection()) }).boxed();
+        };
+        context.statement_started(query.sql());
+        if !context.intercepts() {
+            return conn.fetch_many(query);
+        }
+        let mut statement = Intercepted::new(context, query);
+        let writes = writes(statement.original);
+        // The query borrows the rewritten SQL, so it runs in a future owning
+        // both, which forwards each step without buffering the result set
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let forward = async move {
+            let steps = async {
+                context.fault_statement(&mut *conn).await?;
+                context.budget_statement()?;
+                let (query, recorded) = statement.query(context, &mut *conn).await?;
+                Ok::<_, sqlx::Error>((conn.fetch_many(query), recorded))
+            };
+            let (mut steps, mut recorded) = match steps.await {
+                Ok(steps) => steps,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+            let mut first = true;
+            loop {
+                // Fetch a step only once the previous one was taken
+                let Ok(permit) = sender.reserve().await else {
+                    return;
+                };
+                let step = if first {
+                    first = false;
+                    context.turn(steps.next()).await
+                } else {
+                    steps.next().await
+                };
+                let result = match step {
+                    None => return,
+                    Some(Ok(Either::Left(done))) => {
+                        if writes {
+                            recorded.rows_affected += done.rows_affected();
+                        }
+                        Ok(Either::Left(done))
+                    }
+                    Some(Ok(Either::Right(row))) => {
+                        recorded.rows_returned += 1;
+                        context
+                            .budget_rows(1)
+                            .map(|()| Either::Right(row))
+                            .map_err(Into::into)
+                    }
+                    Some(Err(e)) => Err(e),
+                };
+                let failed = result.is_err();
+                permit.send(result);
+                if failed {
+                    return;
+                }
+            }
+        };
+        let steps = futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        let forward = forward
+            .into_stream()
+            .filter_map(|()| futures_util::future::ready(None));
+        futures_util::stream::select(steps, forward).boxed()
+    }
+
+    fn fetch_optional<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxFuture<'e, Result<Option<PgRow>, sqlx::Error>>
+    where
+        'c: 'e,
+        E: 'q + Execute<'q, Postgres>,
+    {
+        let Self { conn, context } = self;
+        let Some(conn) = conn else {
+            return Box::pin(async { Err(no_connection()) });
+        };
+        context.statement_started(query.sql());
+        if !context.intercepts() {
+            return conn.fetch_optional(query);
+        }
+        let mut statement = Intercepted::new(context, query);
+        let writes = writes(statement.original);
+        Box::pin(async move {
+            context.fault_statement(&mut *conn).await?;
+            context.budget_statement()?;
+            let (query, mut recorded) = statement.query(context, &mut *conn).await?;
+            let row = if writes {
+                // Read up to the command completion, which carries the rows
+                // affected; a write returns every row it affects anyway
+                let mut steps = conn.fetch_many(query);
+                context
+                    .turn(async {
+                        let mut first = None;
+                        while let Some(step) = steps.next().await {
+                            match step? {
+                                Either::Left(done) => {
+                                    recorded.rows_affected += done.rows_affected()
+                                }
+                                Either::Right(row) => {
+                                    recorded.rows_returned += 1;
+                                    first = first.or(Some(row));
+                                }
+                            }
+                        }
+                        Ok::<_, sqlx::Error>(first)
+                    })
+                    .await?
+            } else {
+                let row = context.turn(conn.fetch_optional(query)).await?;
+                recorded.rows_returned = row.is_some() as u64;
+                row
+            };
+            let rows = recorded.rows_returned;
+            drop(recorded);
+            context.budget_rows(rows)?;
+            Ok(row)
+        })
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [PgTypeInfo],
+    ) -> BoxFuture<'e, Result<PgStatement<'q>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        match self.conn {
+            Some(conn) => conn.prepare_with(sql, parameters),
+            None => Box::pin(async { Err(no_connection()) }),
+        }
+    }
+
+    fn describe<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+    ) -> BoxFuture<'e, Result<Describe<Postgres>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        match self.conn {
+            Some(conn) => conn.describe(sql),
+            None => Box::pin(async { Err(no_connection()) }),
+        }
+    }
+}
+
+impl<'c> Acquire<'c> for TxConnection<'c> {
+    type Database = Postgres;
+    type Connection = &'c mut PgConnection;
+
+    fn acquire(self) -> BoxFuture<'c, Result<Self::Connection, sqlx::Error>> {
+        match self.conn {
+            Some(conn) => conn.acquire(),
+            None => Box::pin(async { Err(no_connection()) }),
+        }
+    }
+
+    fn begin(self) -> BoxFuture<'c, Result<sqlx::Transaction<'c, Postgres>, sqlx::Error>> {
+        match self.conn {
+            Some(conn) => conn.begin(),
+            None => Box::pin(async { Err(no_connection()) }),
+        }
+    }
+}
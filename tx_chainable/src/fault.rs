@@ -0,0 +1,253 @@
+use crate::{
+    BoxFuture, ConstraintRegistry, DbErrorKind, GetExecutor, Transaction, Tx, TxContext,
+    TxCoordinator, TxSource,
+};
+use sqlx::error::{DatabaseError, ErrorKind};
+use sqlx::{PgConnection, PgPool};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Faults injected into every transaction begun through a [`Faulty`] repository,
+/// for testing rollback paths and error classification.
+///
+/// Statements are numbered from 1 across the whole transaction, chains
+/// included, so a fault can hit a statement in the middle of a chain. Only
+/// statements run through `Execute::execute` on a Postgres transaction count.
+#[derive(Debug, Clone, Default)]
+pub struct FaultSchedule {
+    faults: Vec<Fault>,
+}
+
+#[derive(Debug, Clone)]
+enum Fault {
+    FailStatement(usize, DbErrorKind),
+    DropConnection(usize),
+    FailCommit(DbErrorKind),
+    Latency(Duration),
+}
+
+impl FaultSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fails statement `n` without running it, with a database error of `kind`.
+    pub fn fail_statement(mut self, n: usize, kind: DbErrorKind) -> Self {
+        self.faults.push(Fault::FailStatement(n, kind));
+        self
+    }
+
+    /// Terminates the backend just before statement `n`, which then fails the
+    /// way a real connection loss does. Every later statement fails too.
+    pub fn drop_connection(mut self, n: usize) -> Self {
+        self.faults.push(Fault::DropConnection(n));
+        self
+    }
+
+    /// Fails COMMIT with a database error of `kind`; the transaction is rolled back.
+    pub fn fail_commit(mut self, kind: DbErrorKind) -> Self {
+        self.faults.push(Fault::FailCommit(kind));
+        self
+    }
+
+    /// Delays every statement by `latency`.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.faults.push(Fault::Latency(latency));
+        self
+    }
+}
+
+/// A [`FaultSchedule`] applied to one transaction.
+#[derive(Debug)]
+pub(crate) struct FaultState {
+    schedule: FaultSchedule,
+    statements: AtomicUsize,
+}
+
+impl FaultState {
+    pub(crate) fn new(schedule: FaultSchedule) -> Self {
+        Self {
+            schedule,
+            statements: AtomicUsize::new(0),
+        }
+    }
+
+    /// Applies the faults due before the next statement runs on `conn`.
+    pub(crate) async fn statement(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        let n = self.statements.fetch_add(1, Ordering::Relaxed) + 1;
+        for fault in &self.schedule.faults {
+            match fault {
+                Fault::Latency(latency) => tokio::time::sleep(*latency).await,
+                Fault::FailStatement(at, kind) if *at == n => {
+                    return Err(InjectedFault::new(kind).into())
+                }
+                Fault::DropConnection(at) if *at == n => {
+                    sqlx::query("SELECT pg_terminate_backend(pg_backend_pid())")
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn commit(&self) -> Result<(), sqlx::Error> {
+        self.schedule
+            .faults
+            .iter()
+            .try_for_each(|fault| match fault {
+                Fault::FailCommit(kind) => Err(InjectedFault::new(kind).into()),
+                _ => Ok(()),
+            })
+    }
+}
+
+/// Database error raised by a [`FaultSchedule`], carrying the SQLSTATE
+/// Postgres uses for the requested [`DbErrorKind`].
+#[derive(Debug)]
+pub struct InjectedFault {
+    message: String,
+    code: &'static str,
+    constraint: Option<String>,
+}
+
+impl InjectedFault {
+    fn new(kind: &DbErrorKind) -> Self {
+        let code = match kind {
+            DbErrorKind::UniqueViolation { .. } => "23505",
+            DbErrorKind::ForeignKeyViolation { .. } => "23503",
+            DbErrorKind::CheckViolation { .. } => "23514",
+            DbErrorKind::SerializationFailure => "40001",
+            DbErrorKind::Deadlock => "40P01",
+            DbErrorKind::LockTimeout => "55P03",
+            DbErrorKind::QueryCanceled => "57014",
+            DbErrorKind::ConnectionLost => "08006",
+        };
+        Self {
+            message: format!("injected {kind}"),
+            code,
+            constraint: kind.constraint().map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+impl DatabaseError for InjectedFault {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.code))
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn constraint(&self) -> Option<&str> {
+        self.constraint.as_deref()
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self.code {
+            "23505" => ErrorKind::UniqueViolation,
+            "23503" => ErrorKind::ForeignKeyViolation,
+            "23514" => ErrorKind::CheckViolation,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Wraps a repository so that every transaction it begins follows a
+/// [`FaultSchedule`].
+///
+/// `Faulty<R>` begins and chains like `R` itself, with the same transactional
+/// repository, constraints and coordinator, and is named after `R` in reports,
+/// spans and metrics.
+#[derive(Debug, Clone)]
+pub struct Faulty<R> {
+    repository: R,
+    schedule: FaultSchedule,
+}
+
+impl<R> Faulty<R> {
+    pub fn new(repository: R, schedule: FaultSchedule) -> Self {
+        Self {
+            repository,
+            schedule,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.repository
+    }
+}
+
+impl<R: Tx> Tx for Faulty<R> {
+    type TxRepository<'tx> = R::TxRepository<'tx>;
+
+    fn constraints() -> &'static ConstraintRegistry {
+        R::constraints()
+    }
+
+    fn name() -> &'static str {
+        R::name()
+    }
+}
+
+impl<'tx, R: GetExecutor<'tx>> GetExecutor<'tx> for Faulty<R> {
+    type Executor = FaultySource<'tx, R::Executor>;
+
+    fn get_executor(&'tx self) -> Self::Executor {
+        FaultySource {
+            source: self.repository.get_executor(),
+            schedule: &self.schedule,
+        }
+    }
+
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        self.repository.coordinator()
+    }
+}
+
+/// Executor of a [`Faulty`] repository: the wrapped repository's executor,
+/// beginning transactions that follow the schedule.
+#[derive(Debug)]
+pub struct FaultySource<'tx, S> {
+    source: S,
+    schedule: &'tx FaultSchedule,
+}
+
+impl<'tx, S: TxSource<'tx>> TxSource<'tx> for FaultySource<'tx, S> {
+    fn begin_transaction(
+        self,
+        context: Arc<TxContext>,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, sqlx::Error>> {
+        context.inject_faults(FaultState::new(self.schedule.clone()));
+        self.source.begin_transaction(context)
+    }
+
+    fn pool(&self) -> Option<&'tx PgPool> {
+        self.source.pool()
+    }
+}
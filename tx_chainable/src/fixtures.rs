@@ -0,0 +1,433 @@
+//! Seeding repositories from YAML or JSON documents, behind the `fixtures` feature.
+//!
+//! A document maps repository keys, as registered on a [`FixtureLoader`], to
+//! lists of rows. Every row is loaded through the registered [`Fixture`] in a
+//! single transaction, in document order, so any failing row rolls back the
+//! whole document:
+//!
+//! ```yaml
+//! users:
+//!   - $alias: alice
+//!     id: 6f1c2a0e-5b7d-4c11-9a43-0d3b2e8f7a10
+//!     name: Alice
+//! events:
+//!   - id: 0b9e4d52-8c3f-4a6e-b1d7-2f5a9c8e6b34
+//!     name: signup
+//!     payload:
+//!       user_id: { $ref: alice.id }
+//! ```
+//!
+//! `$alias` names the row as stored by its fixture, and `{ $ref: alias.field }`
+//! anywhere in a later row is replaced by that stored value (`{ $ref: alias }`
+//! by the whole row).
+
+use crate::trace;
+use crate::{Begin, BoxFuture, Chainable, Erased, Transaction, Tx, TxError, TxRepositoryOf};
+use serde::de::{DeserializeOwned, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+const ALIAS: &str = "$alias";
+const REF: &str = "$ref";
+
+/// A repository that rows of a fixture document can be loaded into.
+pub trait Fixture: Send + Sync + 'static {
+    type Repository: Tx + Send + Sync + 'static;
+    /// A row as written in documents and as stored, e.g. the repository's model.
+    type Row: Serialize + DeserializeOwned + Send + 'static;
+
+    fn repository(&self) -> &Self::Repository;
+
+    /// Inserts `row`, returning the row as stored, which aliases refer to.
+    fn load<'tx>(
+        repository: TxRepositoryOf<'tx, Self::Repository>,
+        row: Self::Row,
+    ) -> BoxFuture<'tx, Result<Loaded<'tx, Self>, TxError>>;
+}
+
+/// The repository to continue with and the row as stored.
+pub type Loaded<'tx, F> = (
+    TxRepositoryOf<'tx, <F as Fixture>::Repository>,
+    <F as Fixture>::Row,
+);
+
+/// Loads fixture documents into registered repositories.
+///
+/// ```ignore
+/// let seeded = FixtureLoader::new()
+///     .fixture("users", UsersRepository::new(pool.clone()))
+///     .fixture("events", EventsRepository::new(pool))
+///     .load_yaml(include_str!("fixtures/demo.yaml"))
+///     .await?;
+/// let alice: User = seeded.get("alice").unwrap();
+/// ```
+#[derive(Default)]
+pub struct FixtureLoader {
+    fixtures: Vec<(String, Arc<dyn DynFixture>)>,
+}
+
+impl FixtureLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the fixture loading the rows under `key`.
+    pub fn fixture<F>(mut self, key: impl Into<String>, fixture: F) -> Self
+    where
+        F: Fixture,
+        for<'tx> F::Repository: Begin<'tx>,
+        for<'tx> TxRepositoryOf<'tx, F::Repository>: Send,
+    {
+        self.fixtures.push((key.into(), Arc::new(Erased(fixture))));
+        self
+    }
+
+    pub async fn load_yaml(&self, document: &str) -> Result<Seeded, FixtureError> {
+        let document: Document = serde_norway::from_str(document).map_err(FixtureError::Yaml)?;
+        self.load_document(document).await
+    }
+
+    pub async fn load_json(&self, document: &str) -> Result<Seeded, FixtureError> {
+        let document: Document = serde_json::from_str(document).map_err(FixtureError::Json)?;
+        self.load_document(document).await
+    }
+
+    /// Loads a parsed document in one transaction, begun by the fixture of
+    /// its first key. Repositories are loaded in the iteration order of the
+    /// map, which is sorted by key unless `serde_json` preserves order.
+    pub async fn load(&self, document: Value) -> Result<Seeded, FixtureError> {
+        let Value::Object(document) = document else {
+            return Err(FixtureError::Malformed(
+                "a document maps repository keys to rows".to_string(),
+            ));
+        };
+        self.load_document(Document(document.into_iter().collect()))
+            .await
+    }
+
+    async fn load_document(&self, document: Document) -> Result<Seeded, FixtureError> {
+        let sections = self.sections(document)?;
+        let Some((first, _)) = sections.first() else {
+            return Ok(Seeded::default());
+        };
+        let aliases = Arc::new(Mutex::new(HashMap::new()));
+        self.fixtures[*first]
+            .1
+            .begin(&sections, &self.fixtures, aliases.clone())
+            .await
+            .map_err(FixtureError::from_tx)?;
+        let rows = std::mem::take(&mut *aliases.lock().unwrap());
+        Ok(Seeded { rows })
+    }
+
+    /// Splits a document into the rows of each registered fixture, in document order.
+    fn sections(&self, document: Document) -> Result<Vec<(usize, Vec<Value>)>, FixtureError> {
+        document
+            .0
+            .into_iter()
+            .map(|(key, rows)| {
+                let Some(index) = self.fixtures.iter().position(|(k, _)| *k == key) else {
+                    return Err(FixtureError::UnknownRepository(key));
+                };
+                match rows {
+                    Value::Array(rows) => Ok((index, rows)),
+                    Value::Null => Ok((index, Vec::new())),
+                    _ => Err(FixtureError::Malformed(format!(
+                        "{key} is not a list of rows"
+                    ))),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The top-level map of a document, in document order, which a
+/// `serde_json::Map` only keeps with its `preserve_order` feature.
+struct Document(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Sections;
+
+        impl<'de> Visitor<'de> for Sections {
+            type Value = Document;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a document mapping repository keys to rows")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Document, A::Error> {
+                let mut sections = Vec::new();
+                while let Some(section) = map.next_entry()? {
+                    sections.push(section);
+                }
+                Ok(Document(sections))
+            }
+        }
+
+        deserializer.deserialize_map(Sections)
+    }
+}
+
+impl fmt::Debug for FixtureLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.fixtures
+                    .iter()
+                    .map(|(key, fixture)| (key, fixture.name())),
+            )
+            .finish()
+    }
+}
+
+/// Rows stored by a loaded document, by alias.
+#[derive(Debug, Clone, Default)]
+pub struct Seeded {
+    rows: HashMap<String, Value>,
+}
+
+impl Seeded {
+    /// The row stored under `alias`, or `None` if there is no such alias or
+    /// it is not a `T`.
+    pub fn get<T: DeserializeOwned>(&self, alias: &str) -> Option<T> {
+        serde_json::from_value(self.rows.get(alias)?.clone()).ok()
+    }
+
+    pub fn value(&self, alias: &str) -> Option<&Value> {
+        self.rows.get(alias)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+/// Error returned by [`FixtureLoader`]. Every error rolls back the whole document.
+#[derive(Debug)]
+pub enum FixtureError {
+    Yaml(serde_norway::Error),
+    Json(serde_json::Error),
+    /// The document is not a map of lists of rows.
+    Malformed(String),
+    /// A document key with no registered fixture.
+    UnknownRepository(String),
+    /// A `$ref` to an alias not defined by an earlier row.
+    UnknownAlias(String),
+    DuplicateAlias(String),
+    /// A row that does not deserialize into its fixture's row type.
+    InvalidRow {
+        repository: String,
+        index: usize,
+        source: serde_json::Error,
+    },
+    /// A fixture failed to load a row.
+    Transaction(TxError),
+}
+
+impl FixtureError {
+    /// Recovers errors raised inside the transaction as domain errors.
+    fn from_tx(error: TxError) -> Self {
+        match error {
+            TxError::Domain(error) => match error.downcast::<FixtureError>() {
+                Ok(error) => *error,
+                Err(error) => Self::Transaction(TxError::Domain(error)),
+            },
+            error => Self::Transaction(error),
+        }
+    }
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Yaml(error) => write!(f, "invalid YAML fixture: {error}"),
+            Self::Json(error) => write!(f, "invalid JSON fixture: {error}"),
+            Self::Malformed(reason) => write!(f, "malformed fixture: {reason}"),
+            Self::UnknownRepository(key) => write!(f, "no fixture registered for {key}"),
+            Self::UnknownAlias(alias) => write!(f, "unknown fixture alias {alias}"),
+            Self::DuplicateAlias(alias) => write!(f, "fixture alias {alias} defined twice"),
+            Self::InvalidRow {
+                repository,
+                index,
+                source,
+            } => write!(f, "invalid row {index} of {repository}: {source}"),
+            Self::Transaction(error) => write!(f, "loading fixtures failed: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for FixtureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Yaml(error) => Some(error),
+            Self::Json(error) | Self::InvalidRow { source: error, .. } => Some(error),
+            Self::Transaction(error) => Some(error),
+            Self::Malformed(_)
+            | Self::UnknownRepository(_)
+            | Self::UnknownAlias(_)
+            | Self::DuplicateAlias(_) => None,
+        }
+    }
+}
+
+impl From<FixtureError> for TxError {
+    fn from(error: FixtureError) -> Self {
+        TxError::Domain(Box::new(error))
+    }
+}
+
+type Aliases = Arc<Mutex<HashMap<String, Value>>>;
+
+type Sections = [(usize, Vec<Value>)];
+
+type Fixtures = [(String, Arc<dyn DynFixture>)];
+
+trait DynFixture: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn begin<'a>(
+        &'a self,
+        sections: &'a Sections,
+        fixtures: &'a Fixtures,
+        aliases: Aliases,
+    ) -> BoxFuture<'a, Result<(), TxError>>;
+
+    fn chain<'tx>(
+        &'tx self,
+        tx: Transaction<'tx>,
+        key: &'tx str,
+        rows: &'tx [Value],
+        aliases: Aliases,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, TxError>>;
+}
+
+impl<F> DynFixture for Erased<F>
+where
+    F: Fixture,
+    for<'tx> F::Repository: Begin<'tx>,
+    for<'tx> TxRepositoryOf<'tx, F::Repository>: Send,
+{
+    fn name(&self) -> &'static str {
+        trace::short_name(F::Repository::name())
+    }
+
+    fn begin<'a>(
+        &'a self,
+        sections: &'a Sections,
+        fixtures: &'a Fixtures,
+        aliases: Aliases,
+    ) -> BoxFuture<'a, Result<(), TxError>> {
+        self.0.repository().begin(move |repo| {
+            Box::pin(async move {
+                let mut tx: Transaction<'_> = repo.into();
+                for (index, rows) in sections {
+                    let (key, fixture) = &fixtures[*index];
+                    tx = fixture.chain(tx, key, rows, aliases.clone()).await?;
+                }
+                Ok(tx.into())
+            })
+        })
+    }
+
+    fn chain<'tx>(
+        &'tx self,
+        tx: Transaction<'tx>,
+        key: &'tx str,
+        rows: &'tx [Value],
+        aliases: Aliases,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, TxError>> {
+        tx.chain(self.0.repository(), move |mut repo| {
+            Box::pin(async move {
+                for (index, row) in rows.iter().enumerate() {
+                    let (alias, row) = resolve(row, &aliases.lock().unwrap())?;
+                    let row = serde_json::from_value::<F::Row>(row).map_err(|source| {
+                        FixtureError::InvalidRow {
+                            repository: key.to_string(),
+                            index,
+                            source,
+                        }
+                    })?;
+                    let (next, stored) = F::load(repo, row).await?;
+                    repo = next;
+                    if let Some(alias) = alias {
+                        let stored = serde_json::to_value(stored).map_err(|source| {
+                            FixtureError::InvalidRow {
+                                repository: key.to_string(),
+                                index,
+                                source,
+                            }
+                        })?;
+                        aliases.lock().unwrap().insert(alias, stored);
+                    }
+                }
+                Ok(repo)
+            })
+        })
+    }
+}
+
+/// Takes the alias off a row and replaces its references.
+fn resolve(
+    row: &Value,
+    aliases: &HashMap<String, Value>,
+) -> Result<(Option<String>, Value), FixtureError> {
+    let mut row = row.clone();
+    let alias = match row.as_object_mut().and_then(|row| row.remove(ALIAS)) {
+        None => None,
+        Some(Value::String(alias)) if aliases.contains_key(&alias) => {
+            return Err(FixtureError::DuplicateAlias(alias))
+        }
+        Some(Value::String(alias)) => Some(alias),
+        Some(alias) => {
+            return Err(FixtureError::Malformed(format!(
+                "{ALIAS} must be a string, not {alias}"
+            )))
+        }
+    };
+    Ok((alias, substitute(row, aliases)?))
+}
+
+fn substitute(value: Value, aliases: &HashMap<String, Value>) -> Result<Value, FixtureError> {
+    match value {
+        Value::Object(map) if map.len() == 1 && map.contains_key(REF) => match &map[REF] {
+            Value::String(path) => lookup(path, aliases),
+            path => Err(FixtureError::Malformed(format!(
+                "{REF} must be a string, not {path}"
+            ))),
+        },
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| Ok((key, substitute(value, aliases)?)))
+            .collect::<Result<Map<_, _>, _>>()
+            .map(Value::Object),
+        Value::Array(values) => values
+            .into_iter()
+            .map(|value| substitute(value, aliases))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        value => Ok(value),
+    }
+}
+
+/// Resolves `alias.field.field` against the stored rows.
+fn lookup(path: &str, aliases: &HashMap<String, Value>) -> Result<Value, FixtureError> {
+    let mut parts = path.split('.');
+    let alias = parts.next().unwrap_or_default();
+    let mut value = aliases
+        .get(alias)
+        .ok_or_else(|| FixtureError::UnknownAlias(alias.to_string()))?;
+    for field in parts {
+        value = value
+            .get(field)
+            .ok_or_else(|| FixtureError::Malformed(format!("{path}: {alias} has no {field}")))?;
+    }
+    Ok(value.clone())
+}
@@ -0,0 +1,162 @@
+use crate::TxContext;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, SystemTime};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static OPEN: LazyLock<Mutex<HashMap<u64, Arc<TxContext>>>> = LazyLock::new(Default::default);
+
+/// Process-wide registry of the transactions currently open through `Begin`.
+///
+/// Disabled by default, since tracking the backend PID costs a round trip
+/// at the start of every transaction.
+pub struct InFlightRegistry;
+
+impl InFlightRegistry {
+    pub fn enable() {
+        ENABLED.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled() -> bool {
+        ENABLED.load(Ordering::Relaxed)
+    }
+
+    /// Open transactions, oldest first.
+    pub fn snapshot() -> Vec<InFlightTx> {
+        let open = OPEN.lock().unwrap();
+        let mut snapshot: Vec<_> = open
+            .iter()
+            .map(|(id, context)| InFlightTx::new(*id, context))
+            .collect();
+        snapshot.sort_by_key(|tx| std::cmp::Reverse(tx.age));
+        snapshot
+    }
+
+    /// Ends the transaction by terminating its backend through
+    /// `pg_terminate_backend` on a connection from `pool`. Postgres rolls it
+    /// back and releases its locks at once, whether it is running a statement
+    /// or idle in transaction. Its current or next statement fails with
+    /// [`DbErrorKind::ConnectionLost`](crate::DbErrorKind::ConnectionLost).
+    ///
+    /// Returns `false` if the transaction is no longer open or has no
+    /// connection yet.
+    pub async fn cancel(id: u64, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        let pid = OPEN
+            .lock()
+            .unwrap()
+            .get(&id)
+            .and_then(|context| context.backend_pid());
+        let Some(pid) = pid else {
+            return Ok(false);
+        };
+        sqlx::query_scalar("SELECT pg_terminate_backend($1)")
+            .bind(pid)
+            .fetch_one(pool)
+            .await
+    }
+
+    /// Adds `context` to the registry until the returned guard is dropped.
+    pub(crate) fn register(context: &Arc<TxContext>) -> Option<Registration> {
+        if !Self::is_enabled() {
+            return None;
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        OPEN.lock().unwrap().insert(id, context.clone());
+        Some(Registration { id })
+    }
+}
+
+pub(crate) struct Registration {
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        OPEN.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// An open transaction, as seen by [`InFlightRegistry::snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct InFlightTx {
+    pub id: u64,
+    pub name: String,
+    pub started_at: SystemTime,
+    pub age: Duration,
+    /// `None` while waiting for a connection.
+    pub backend_pid: Option<i32>,
+    pub chain_path: String,
+    pub last_statement: Option<String>,
+}
+
+impl InFlightTx {
+    fn new(id: u64, context: &TxContext) -> Self {
+        Self {
+            id,
+            name: context.name().to_string(),
+            started_at: context.started_at(),
+            age: context.age(),
+            backend_pid: context.backend_pid(),
+            chain_path: context.chain_path(),
+            last_statement: context.last_statement(),
+        }
+    }
+}
+
+/// Reports transactions open for longer than a threshold.
+///
+/// Spawn [`Watchdog::run`] on the runtime, or call [`Watchdog::check`] from
+/// your own scheduler.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    threshold: Duration,
+    interval: Duration,
+}
+
+impl Watchdog {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            interval: Duration::from_secs(10),
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Logs a warning for every transaction over the threshold and returns them.
+    pub fn check(&self) -> Vec<InFlightTx> {
+        let long_running: Vec<_> = InFlightRegistry::snapshot()
+            .into_iter()
+            .filter(|tx| tx.age > self.threshold)
+            .collect();
+        #[cfg(feature = "tracing")]
+        for tx in &long_running {
+            tracing::warn!(
+                id = tx.id,
+                transaction = %tx.name,
+                age_ms = tx.age.as_millis() as u64,
+                backend_pid = tx.backend_pid,
+                chain = %tx.chain_path,
+                last_statement = tx.last_statement.as_deref(),
+                "long-running transaction"
+            );
+        }
+        #[cfg(feature = "metrics")]
+        metrics::gauge!("tx_chainable_long_running_transactions").set(long_running.len() as f64);
+        long_running
+    }
+
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+}
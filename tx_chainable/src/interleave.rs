@@ -0,0 +1,244 @@
+use crate::{IsolationLevel, TxOptions};
+use futures_util::future::{select, Either};
+use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Runs concurrent transactions in a fixed interleaving, to reproduce
+/// anomalies such as lost updates, write skew and phantom reads.
+///
+/// Each transaction is labelled; the order lists whose turn it is to run its
+/// next statement through `Execute::execute`, COMMIT included. A turn ends
+/// when the statement completes or, if it blocks on a lock, after the step
+/// timeout; later turns of a blocked transaction are skipped until the
+/// statement returns. Turns of a finished transaction are skipped, and once
+/// the order is exhausted statements run freely.
+///
+/// ```ignore
+/// let interleaving = Interleaving::new(["a", "b", "a", "b", "a", "b"]);
+/// let report = interleaving
+///     .run(IsolationLevel::ALL, |schedule| async move {
+///         let (a, b) = tokio::join!(
+///             repo.begin_with(schedule.options("a"), withdraw(70)),
+///             repo.begin_with(schedule.options("b"), withdraw(70)),
+///         );
+///         check_balance_not_negative(&pool).await
+///     })
+///     .await;
+/// assert_eq!(Some(IsolationLevel::Serializable), report.weakest_safe());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Interleaving {
+    order: Vec<&'static str>,
+    step_timeout: Duration,
+}
+
+impl Interleaving {
+    pub fn new(order: impl IntoIterator<Item = &'static str>) -> Self {
+        Self {
+            order: order.into_iter().collect(),
+            step_timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// How long a statement may run before it counts as blocked and the next
+    /// turn begins. Defaults to 100ms.
+    pub fn step_timeout(mut self, timeout: Duration) -> Self {
+        self.step_timeout = timeout;
+        self
+    }
+
+    /// Runs the scenario once per isolation level, each time with a fresh
+    /// [`Schedule`] of this interleaving.
+    ///
+    /// The scenario begins its transactions with [`Schedule::options`], waits
+    /// for them and returns `Err` describing the anomaly if it observed one.
+    /// It must reset any data it relies on, since every level sees the
+    /// previous level's commits.
+    pub async fn run<F, Fut>(
+        &self,
+        levels: impl IntoIterator<Item = IsolationLevel>,
+        mut scenario: F,
+    ) -> AnomalyReport
+    where
+        F: FnMut(Schedule) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let mut levels_run = Vec::new();
+        for isolation in levels {
+            let schedule = self.schedule(isolation);
+            let anomaly = scenario(schedule).await.err();
+            levels_run.push(LevelReport { isolation, anomaly });
+        }
+        AnomalyReport { levels: levels_run }
+    }
+
+    /// A single run of this interleaving at `isolation`.
+    pub fn schedule(&self, isolation: IsolationLevel) -> Schedule {
+        let (state, _) = watch::channel(State::default());
+        Schedule {
+            isolation,
+            shared: Arc::new(Shared {
+                order: self.order.clone(),
+                step_timeout: self.step_timeout,
+                state,
+            }),
+        }
+    }
+}
+
+/// One run of an [`Interleaving`] at one isolation level.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    isolation: IsolationLevel,
+    shared: Arc<Shared>,
+}
+
+impl Schedule {
+    pub fn isolation(&self) -> IsolationLevel {
+        self.isolation
+    }
+
+    /// Options for the transaction taking the turns labelled `label`.
+    pub fn options(&self, label: &'static str) -> TxOptions {
+        TxOptions::new().isolation(self.isolation).turns(Turns {
+            label,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    order: Vec<&'static str>,
+    step_timeout: Duration,
+    state: watch::Sender<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    cursor: usize,
+    blocked: HashSet<&'static str>,
+    finished: HashSet<&'static str>,
+}
+
+impl Shared {
+    /// Moves past the current turn, then past turns nobody can take.
+    fn advance(&self, state: &mut State) {
+        state.cursor += 1;
+        self.skip(state);
+    }
+
+    fn skip(&self, state: &mut State) {
+        while let Some(label) = self.order.get(state.cursor) {
+            if !state.blocked.contains(label) && !state.finished.contains(label) {
+                break;
+            }
+            state.cursor += 1;
+        }
+    }
+}
+
+/// The turns of one transaction in a [`Schedule`], set through
+/// [`TxOptions::turns`](crate::TxOptions::turns).
+#[derive(Debug)]
+pub struct Turns {
+    label: &'static str,
+    shared: Arc<Shared>,
+}
+
+impl Turns {
+    /// Runs a statement (or COMMIT) in this transaction's next turn.
+    pub(crate) async fn take<T>(&self, statement: impl Future<Output = T>) -> T {
+        let shared = &self.shared;
+        let mut turn = shared.state.subscribe();
+        let _ = turn
+            .wait_for(|state| {
+                shared
+                    .order
+                    .get(state.cursor)
+                    .is_none_or(|label| *label == self.label)
+            })
+            .await;
+        let in_turn = shared.state.borrow().cursor < shared.order.len();
+        if !in_turn {
+            return statement.await;
+        }
+        let statement = pin!(statement);
+        let timeout = pin!(tokio::time::sleep(shared.step_timeout));
+        match select(statement, timeout).await {
+            Either::Left((output, _)) => {
+                shared.state.send_modify(|state| shared.advance(state));
+                output
+            }
+            Either::Right(((), statement)) => {
+                shared.state.send_modify(|state| {
+                    state.blocked.insert(self.label);
+                    shared.advance(state);
+                });
+                let output = statement.await;
+                shared.state.send_modify(|state| {
+                    state.blocked.remove(self.label);
+                });
+                output
+            }
+        }
+    }
+}
+
+impl Drop for Turns {
+    fn drop(&mut self) {
+        let shared = &self.shared;
+        shared.state.send_modify(|state| {
+            state.finished.insert(self.label);
+            shared.skip(state);
+        });
+    }
+}
+
+/// Whether each isolation level showed an anomaly under an [`Interleaving`].
+#[derive(Debug, Clone)]
+pub struct AnomalyReport {
+    pub levels: Vec<LevelReport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelReport {
+    pub isolation: IsolationLevel,
+    /// The anomaly the scenario observed, if any.
+    pub anomaly: Option<String>,
+}
+
+impl AnomalyReport {
+    /// Whether the scenario ran at `isolation` without an anomaly.
+    pub fn is_safe_at(&self, isolation: IsolationLevel) -> bool {
+        self.levels
+            .iter()
+            .any(|level| level.isolation == isolation && level.anomaly.is_none())
+    }
+
+    /// The weakest level that ran without an anomaly.
+    pub fn weakest_safe(&self) -> Option<IsolationLevel> {
+        self.levels
+            .iter()
+            .filter(|level| level.anomaly.is_none())
+            .map(|level| level.isolation)
+            .min()
+    }
+}
+
+impl fmt::Display for AnomalyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for level in &self.levels {
+            match &level.anomaly {
+                Some(anomaly) => writeln!(f, "{}: {anomaly}", level.isolation)?,
+                None => writeln!(f, "{}: safe", level.isolation)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,56 @@
-use sqlx::{Acquire, PgExecutor, PgPool, PgTransaction};
+#[cfg(feature = "proptest")]
+pub mod atomicity;
+mod breaker;
+mod budget;
+mod bulkhead;
+#[cfg(feature = "cassette")]
+pub mod cassette;
+mod coordinator;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+mod error;
+mod escape;
+mod executor;
+mod fault;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+mod in_flight;
+#[cfg(feature = "test")]
+mod interleave;
+#[cfg(feature = "listener")]
+pub mod listener;
+mod memory;
+mod meter;
+mod report;
+#[cfg(feature = "test")]
+pub mod testing;
+mod trace;
+mod transaction;
+
+pub use breaker::{CircuitBreaker, CircuitState};
+pub use budget::{BudgetAction, BudgetExceeded, BudgetLimit, TxBudget};
+pub use bulkhead::Bulkhead;
+pub use coordinator::{ShutdownReport, TxCoordinator};
+pub use error::{BoxDynError, ConstraintRegistry, DbErrorKind, TxError};
+pub use escape::{EscapeAction, EscapeDetector};
+pub use executor::TxConnection;
+pub use fault::{FaultSchedule, Faulty, FaultySource, InjectedFault};
+pub use in_flight::{InFlightRegistry, InFlightTx, Watchdog};
+#[cfg(feature = "test")]
+pub use interleave::{AnomalyReport, Interleaving, LevelReport, Schedule, Turns};
+pub use memory::{MemoryError, MemoryExecute, MemoryStore, MemoryTransaction};
+pub use report::{StatementReport, TxReport};
+pub use transaction::{IsolationLevel, Transaction, TxContext, TxOptions, TxSource};
+#[cfg(feature = "test")]
+pub use tx_chainable_test::test;
+
+use meter::TxMeter;
+use sqlx::{Acquire, PgExecutor, PgPool};
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use trace::TxSpan;
+use transaction::Unfinished;
 
 pub type BoxFuture<'tx, T> = Pin<Box<dyn Future<Output = T> + Send + 'tx>>;
 
@@ -29,6 +79,9 @@ impl Execute for PgPool {
         Fut: Future<Output = T> + Send,
         T: Send,
     {
+        EscapeDetector::check("PgPool::execute");
+        #[cfg(feature = "tracing")]
+        tracing::debug!(executor = "pool", "execute");
         f(self) // &PgPool implements Executor
     }
 }
@@ -45,34 +98,69 @@ impl<'t> Execute for sqlx::PgTransaction<'t> {
         Fut: Future<Output = T> + Send,
         T: Send,
     {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(executor = "transaction", "execute");
         f(self.as_mut())
     }
 }
 
 pub trait GetExecutor<'tx> {
-    type Executor: sqlx::PgExecutor<'tx>;
+    type Executor;
     fn get_executor(&'tx self) -> Self::Executor;
+
+    /// Coordinator that admits this repository's transactions, if any.
+    fn coordinator(&'tx self) -> Option<&'tx TxCoordinator> {
+        None
+    }
 }
 
+static NO_CONSTRAINTS: ConstraintRegistry = ConstraintRegistry::new();
+
 pub trait Tx {
-    type TxRepository<'tx>: From<PgTransaction<'tx>> + Into<PgTransaction<'tx>>;
+    type TxRepository<'tx>: From<Transaction<'tx>> + Into<Transaction<'tx>>;
+
+    /// Constraint mappings applied to errors returned from this repository's closures.
+    fn constraints() -> &'static ConstraintRegistry {
+        &NO_CONSTRAINTS
+    }
+
+    /// What reports, spans, metrics and bulkheads call this repository,
+    /// by default its type path without generics. Wrappers return the name
+    /// of the repository they wrap.
+    fn name() -> &'static str {
+        trace::type_path::<Self>()
+    }
+}
+
+/// The transactional repository of `R`, for signatures generic over
+/// repositories, such as those of the atomicity harness and fixtures.
+pub type TxRepositoryOf<'tx, R> = <R as Tx>::TxRepository<'tx>;
+
+/// A bare transaction chains into any repository, which type-erased callers
+/// use when they no longer know the repository holding it.
+impl Tx for Transaction<'_> {
+    type TxRepository<'tx> = Transaction<'tx>;
 }
 
+/// A value behind one of the crate's type-erasing traits.
+#[cfg(any(feature = "proptest", feature = "fixtures"))]
+struct Erased<T>(T);
+
 pub trait Chainable<'tx>: Tx {
     fn chain<Other, F>(
         self,
         other: &Other,
         f: F,
-    ) -> BoxFuture<'tx, Result<Self::TxRepository<'tx>, sqlx::Error>>
+    ) -> BoxFuture<'tx, Result<Self::TxRepository<'tx>, TxError>>
     where
         Other: Tx,
-        Other::TxRepository<'tx>: From<PgTransaction<'tx>>,
-        Other::TxRepository<'tx>: Into<PgTransaction<'tx>> + Send + 'tx,
+        Other::TxRepository<'tx>: From<Transaction<'tx>>,
+        Other::TxRepository<'tx>: Into<Transaction<'tx>> + Send + 'tx,
         F: FnOnce(
             Other::TxRepository<'tx>,
         ) -> BoxFuture<
             'tx,
-            Result<Other::TxRepository<'tx>, sqlx::Error>,
+            Result<Other::TxRepository<'tx>, TxError>,
         >,
         Self: Sized;
 }
@@ -80,32 +168,46 @@ pub trait Chainable<'tx>: Tx {
 impl<'tx, R> Chainable<'tx> for R
 where
     R: Tx,
-    R: Into<PgTransaction<'tx>>,
+    R: Into<Transaction<'tx>>,
 {
     fn chain<Other, F>(
         self,
         _: &Other,
         f: F,
-    ) -> BoxFuture<'tx, Result<Self::TxRepository<'tx>, sqlx::Error>>
+    ) -> BoxFuture<'tx, Result<Self::TxRepository<'tx>, TxError>>
     where
         Other: Tx,
-        Other::TxRepository<'tx>: From<PgTransaction<'tx>>,
-        Other::TxRepository<'tx>: Into<PgTransaction<'tx>> + Send + 'tx,
+        Other::TxRepository<'tx>: From<Transaction<'tx>>,
+        Other::TxRepository<'tx>: Into<Transaction<'tx>> + Send + 'tx,
         F: FnOnce(
             Other::TxRepository<'tx>,
         ) -> BoxFuture<
             'tx,
-            Result<Other::TxRepository<'tx>, sqlx::Error>,
+            Result<Other::TxRepository<'tx>, TxError>,
         >,
         Self: Sized,
     {
         let tx = self.into();
+        let context = tx.shared_context();
+        context.enter_chain(trace::short_name(Other::name()));
+        TxMeter::chain(&context);
         let repo = <Other as Tx>::TxRepository::from(tx);
-        let fut = f(repo);
+        let span = TxSpan::chain(
+            trace::short_name(Self::name()),
+            trace::short_name(Other::name()),
+        );
+        let fut = span.in_scope(|| f(repo));
         Box::pin(async move {
-            let repo_result = fut.await?;
-            let tx = repo_result.into();
-            Ok(Self::TxRepository::from(tx)) // This assumes From<PgTransaction>
+            let result = span
+                .scope(async move {
+                    let repo_result = fut.await.map_err(|e| Other::constraints().apply(e))?;
+                    let tx = repo_result.into();
+                    context.exit_chain();
+                    Ok(Self::TxRepository::from(tx)) // This assumes From<Transaction>
+                })
+                .await;
+            span.finish(&result);
+            result
         })
     }
 }
@@ -114,45 +216,255 @@ pub trait Begin<'tx>: Tx {
     fn begin<F>(
         &'tx self,
         f: F,
-    ) -> BoxFuture<'tx, Result<(), sqlx::Error>>
+    ) -> BoxFuture<'tx, Result<(), TxError>>
+    where
+        F: FnOnce(
+                Self::TxRepository<'tx>,
+            ) -> BoxFuture<
+                'tx,
+                Result<Self::TxRepository<'tx>, TxError>,
+            > + Send
+            + 'tx,
+        Self: Sized,
+    {
+        self.begin_with(TxOptions::default(), f)
+    }
+
+    fn begin_with<F>(
+        &'tx self,
+        options: TxOptions,
+        f: F,
+    ) -> BoxFuture<'tx, Result<(), TxError>>
+    where
+        F: FnOnce(
+                Self::TxRepository<'tx>,
+            ) -> BoxFuture<
+                'tx,
+                Result<Self::TxRepository<'tx>, TxError>,
+            > + Send
+            + 'tx,
+        Self: Sized;
+
+    /// Like [`Begin::begin_with`], also recording every statement in a [`TxReport`].
+    fn begin_with_report<F>(
+        &'tx self,
+        options: TxOptions,
+        f: F,
+    ) -> BoxFuture<'tx, (Result<(), TxError>, TxReport)>
+    where
+        F: FnOnce(
+                Self::TxRepository<'tx>,
+            ) -> BoxFuture<
+                'tx,
+                Result<Self::TxRepository<'tx>, TxError>,
+            > + Send
+            + 'tx,
+        Self: Sized;
+
+    /// Runs `f` with full chain support, then always rolls back, returning
+    /// the value `f` produced and a [`TxReport`] of what it would have changed.
+    ///
+    /// [`TxContext::is_dry_run`] lets repositories skip side effects that
+    /// would escape the rollback, such as outbox enqueues.
+    fn begin_dry_run<F, T>(
+        &'tx self,
+        f: F,
+    ) -> BoxFuture<'tx, Result<(T, TxReport), TxError>>
+    where
+        F: FnOnce(
+                Self::TxRepository<'tx>,
+            ) -> BoxFuture<
+                'tx,
+                Result<(Self::TxRepository<'tx>, T), TxError>,
+            > + Send
+            + 'tx,
+        T: Send + 'tx,
+        Self: Sized,
+    {
+        self.begin_dry_run_with(TxOptions::default(), f)
+    }
+
+    fn begin_dry_run_with<F, T>(
+        &'tx self,
+        options: TxOptions,
+        f: F,
+    ) -> BoxFuture<'tx, Result<(T, TxReport), TxError>>
     where
         F: FnOnce(
                 Self::TxRepository<'tx>,
             ) -> BoxFuture<
                 'tx,
-                Result<Self::TxRepository<'tx>, sqlx::Error>,
+                Result<(Self::TxRepository<'tx>, T), TxError>,
             > + Send
             + 'tx,
+        T: Send + 'tx,
         Self: Sized;
 }
 
 impl<'tx, R> Begin<'tx> for R
 where
     R: Tx + GetExecutor<'tx>,
-    R::Executor: sqlx::Acquire<'tx, Database = sqlx::Postgres>,
+    R::Executor: TxSource<'tx>,
 {
-    fn begin<F>(
+    fn begin_with<F>(
         &'tx self, // Now we can take &mut self
+        options: TxOptions,
+        f: F,
+    ) -> BoxFuture<'tx, Result<(), TxError>>
+    where
+        F: FnOnce(
+                Self::TxRepository<'tx>,
+            ) -> BoxFuture<
+                'tx,
+                Result<Self::TxRepository<'tx>, TxError>,
+            > + Send
+            + 'tx,
+        Self: Sized,
+    {
+        let context = options.into_context(Self::name(), false);
+        let fut = run(self, context, with_unit(f));
+        Box::pin(async move { fut.await.0 })
+    }
+
+    fn begin_with_report<F>(
+        &'tx self,
+        options: TxOptions,
         f: F,
-    ) -> BoxFuture<'tx, Result<(), sqlx::Error>>
+    ) -> BoxFuture<'tx, (Result<(), TxError>, TxReport)>
     where
         F: FnOnce(
                 Self::TxRepository<'tx>,
             ) -> BoxFuture<
                 'tx,
-                Result<Self::TxRepository<'tx>, sqlx::Error>,
+                Result<Self::TxRepository<'tx>, TxError>,
             > + Send
             + 'tx,
         Self: Sized,
     {
-        let executor = self.get_executor();
-        let fut = executor.begin();
+        let context = options.into_context(Self::name(), true);
+        run(self, context, with_unit(f))
+    }
+
+    fn begin_dry_run_with<F, T>(
+        &'tx self,
+        options: TxOptions,
+        f: F,
+    ) -> BoxFuture<'tx, Result<(T, TxReport), TxError>>
+    where
+        F: FnOnce(
+                Self::TxRepository<'tx>,
+            ) -> BoxFuture<
+                'tx,
+                Result<(Self::TxRepository<'tx>, T), TxError>,
+            > + Send
+            + 'tx,
+        T: Send + 'tx,
+        Self: Sized,
+    {
+        let context = options
+            .into_context(Self::name(), true)
+            .into_dry_run();
+        let fut = run(self, context, f);
         Box::pin(async move {
-            let tx = fut.await?;
-            let ret = f(Self::TxRepository::from(tx)).await?;
-            let committed_tx = ret.into();
-            committed_tx.commit().await?;
-            Ok(())
+            let (result, report) = fut.await;
+            result.map(|value| (value, report))
         })
     }
 }
+
+/// Adapts a `begin` closure to [`run`], which also threads a value out of it.
+fn with_unit<'tx, Repo, F>(
+    f: F,
+) -> impl FnOnce(Repo) -> BoxFuture<'tx, Result<(Repo, ()), TxError>> + Send + 'tx
+where
+    Repo: 'tx,
+    F: FnOnce(Repo) -> BoxFuture<'tx, Result<Repo, TxError>> + Send + 'tx,
+{
+    move |repo| {
+        let fut = f(repo);
+        Box::pin(async move { fut.await.map(|repo| (repo, ())) })
+    }
+}
+
+/// Runs `f` in a new transaction, committing if it succeeds (or rolling back
+/// anyway for a dry run) and returning the value `f` produced.
+///
+/// The report is empty unless `context` records statements.
+fn run<'tx, R, F, T>(
+    repository: &'tx R,
+    context: TxContext,
+    f: F,
+) -> BoxFuture<'tx, (Result<T, TxError>, TxReport)>
+where
+    R: Tx + GetExecutor<'tx>,
+    R::Executor: TxSource<'tx>,
+    F: FnOnce(R::TxRepository<'tx>) -> BoxFuture<'tx, Result<(R::TxRepository<'tx>, T), TxError>>
+        + Send
+        + 'tx,
+    T: Send + 'tx,
+{
+    let coordinator = repository.coordinator();
+    let context = Arc::new(context);
+    // Lazy: nothing runs until admission succeeds
+    let fut = repository.get_executor().begin_transaction(context.clone());
+    let pool = repository.get_executor().pool();
+    Box::pin(async move {
+        let admission = match coordinator {
+            Some(coordinator) => match coordinator.admit(&context).await {
+                Ok(admission) => Some(admission),
+                Err(e) => return (Err(e), TxReport::default()),
+            },
+            None => None,
+        };
+        let registration = InFlightRegistry::register(&context);
+        let span = TxSpan::begin(context.repository(), context.is_dry_run());
+        let meter = TxMeter::begin(&context);
+        let meter_ref = &meter;
+        let unfinished = Arc::new(Unfinished::default());
+        let body_unfinished = unfinished.clone();
+        let body = async move {
+            let mut tx = match coordinator {
+                Some(coordinator) => coordinator.begin(fut, pool).await?,
+                None => fut.await?,
+            };
+            tx.on_drop(body_unfinished);
+            tx.set_up().await?;
+            let (ret, value) = f(R::TxRepository::from(tx)).await.map_err(|e| {
+                meter_ref.rolled_back();
+                R::constraints().apply(e)
+            })?;
+            let tx: Transaction<'tx> = ret.into();
+            if let Some(operation) = EscapeDetector::escaped() {
+                meter_ref.rolled_back();
+                tx.rollback().await?;
+                return Err(TxError::Escaped { operation });
+            }
+            if tx.context().is_dry_run() {
+                tx.rollback().await?;
+            } else {
+                tx.commit()
+                    .await
+                    .map_err(|e| R::constraints().apply(e.into()))?;
+            }
+            Ok(value)
+        };
+        let scoped = span.scope(async move {
+            let result = match &admission {
+                Some(admission) => admission.run(body, unfinished.rollback()).await,
+                None => body.await,
+            };
+            // `f` failed, dropping the transaction it was given
+            unfinished.rollback().await;
+            result
+        });
+        let result = EscapeDetector::scope(&context, scoped).await;
+        let report = match context.recording() {
+            Some(recording) => recording.report(context.hops(), context.connection_held()),
+            None => TxReport::default(),
+        };
+        drop(registration);
+        span.finish(&result);
+        meter.finish(&context, &result);
+        (result, report)
+    })
+}
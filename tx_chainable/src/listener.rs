@@ -0,0 +1,215 @@
+//! Typed LISTEN/NOTIFY consumers, behind the `listener` feature.
+//!
+//! Repositories send notifications with [`Transaction::notify`], so they are
+//! delivered only once the chain commits. A [`Listener`] receives them with
+//! JSON payloads decoded into `T`. Postgres does not queue notifications for
+//! a listener whose connection is down, so the listener reconnects on its
+//! own and then runs its catch-up hook, where consumers reconcile what they
+//! may have missed from the tables themselves:
+//!
+//! ```ignore
+//! let mut listener = Listener::<EventAppended>::connect(&pool, &["event_store"])
+//!     .await?
+//!     .on_reconnect(move || reload_from(pool.clone()));
+//! while let Ok(notification) = listener.recv().await {
+//!     handle(notification.payload);
+//! }
+//! ```
+//!
+//! [`Transaction::notify`]: crate::Transaction::notify
+
+use crate::{BoxDynError, BoxFuture};
+use serde::de::DeserializeOwned;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// A notification received by a [`Listener`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification<T> {
+    pub channel: String,
+    /// The backend process of the transaction that sent it.
+    pub process_id: u32,
+    pub payload: T,
+}
+
+type CatchUp = Box<dyn FnMut() -> BoxFuture<'static, Result<(), BoxDynError>> + Send>;
+
+/// Receives notifications on a set of channels, decoding their payloads as
+/// JSON into `T`.
+pub struct Listener<T> {
+    pool: PgPool,
+    channels: Vec<String>,
+    listener: Option<PgListener>,
+    catch_up: Option<CatchUp>,
+    catching_up: bool,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+    payload: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for Listener<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Listener")
+            .field("channels", &self.channels)
+            .field("connected", &self.listener.is_some())
+            .field("catching_up", &self.catching_up)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: DeserializeOwned> Listener<T> {
+    /// Connects with the options of `pool` and listens on `channels`.
+    pub async fn connect(pool: &PgPool, channels: &[&str]) -> Result<Self, ListenerError> {
+        let channels: Vec<String> = channels.iter().map(|channel| channel.to_string()).collect();
+        let listener = listen(pool, &channels)
+            .await
+            .map_err(ListenerError::Database)?;
+        Ok(Self {
+            pool: pool.clone(),
+            channels,
+            listener: Some(listener),
+            catch_up: None,
+            catching_up: false,
+            reconnect_delay: Duration::from_millis(100),
+            max_reconnect_delay: Duration::from_secs(5),
+            payload: PhantomData,
+        })
+    }
+
+    /// Runs `catch_up` after every reconnect, once the channels are listened
+    /// on again, so nothing sent after it started is missed. It is not run
+    /// after the initial connect.
+    ///
+    /// If it fails, [`recv`](Self::recv) returns [`ListenerError::CatchUp`]
+    /// and the next call runs it again.
+    pub fn on_reconnect<F, Fut>(mut self, mut catch_up: F) -> Self
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), BoxDynError>> + Send + 'static,
+    {
+        self.catch_up = Some(Box::new(move || Box::pin(catch_up())));
+        self
+    }
+
+    /// The wait before the first reconnect attempt, doubled after each failed
+    /// attempt up to `max`. Defaults to 100ms and 5s.
+    pub fn reconnect_delay(mut self, initial: Duration, max: Duration) -> Self {
+        self.reconnect_delay = initial;
+        self.max_reconnect_delay = max;
+        self
+    }
+
+    /// Waits for the next notification, reconnecting as often as needed.
+    ///
+    /// A payload that does not decode fails with [`ListenerError::Payload`];
+    /// the listener stays usable.
+    pub async fn recv(&mut self) -> Result<Notification<T>, ListenerError> {
+        loop {
+            if self.catching_up {
+                if let Some(catch_up) = &mut self.catch_up {
+                    catch_up().await.map_err(ListenerError::CatchUp)?;
+                }
+                self.catching_up = false;
+            }
+            let Some(listener) = &mut self.listener else {
+                self.reconnect().await?;
+                continue;
+            };
+            match listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    let channel = notification.channel().to_string();
+                    let process_id = notification.process_id();
+                    return match serde_json::from_str(notification.payload()) {
+                        Ok(payload) => Ok(Notification {
+                            channel,
+                            process_id,
+                            payload,
+                        }),
+                        Err(source) => Err(ListenerError::Payload {
+                            channel,
+                            payload: notification.payload().to_string(),
+                            source,
+                        }),
+                    };
+                }
+                // The connection was lost; notifications sent meanwhile are gone
+                Ok(None) | Err(_) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(channels = ?self.channels, "listener connection lost");
+                    self.listener = None;
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ListenerError> {
+        let mut delay = self.reconnect_delay;
+        loop {
+            if self.pool.is_closed() {
+                return Err(ListenerError::Database(sqlx::Error::PoolClosed));
+            }
+            match listen(&self.pool, &self.channels).await {
+                Ok(listener) => {
+                    self.listener = Some(listener);
+                    self.catching_up = true;
+                    return Ok(());
+                }
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(channels = ?self.channels, error = %_error, "listener reconnect failed");
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(self.max_reconnect_delay);
+                }
+            }
+        }
+    }
+}
+
+async fn listen(pool: &PgPool, channels: &[String]) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener
+        .listen_all(channels.iter().map(String::as_str))
+        .await?;
+    Ok(listener)
+}
+
+/// Failures receiving notifications.
+#[derive(Debug)]
+pub enum ListenerError {
+    /// Connecting failed, or the pool was closed.
+    Database(sqlx::Error),
+    /// A payload that does not decode into the listener's payload type.
+    Payload {
+        channel: String,
+        payload: String,
+        source: serde_json::Error,
+    },
+    /// The catch-up hook failed after a reconnect.
+    CatchUp(BoxDynError),
+}
+
+impl fmt::Display for ListenerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database(error) => write!(f, "listener connection failed: {error}"),
+            Self::Payload {
+                channel, source, ..
+            } => write!(f, "invalid notification payload on {channel}: {source}"),
+            Self::CatchUp(error) => write!(f, "listener catch-up failed: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ListenerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(error) => Some(error),
+            Self::Payload { source, .. } => Some(source),
+            Self::CatchUp(error) => Some(error.as_ref()),
+        }
+    }
+}
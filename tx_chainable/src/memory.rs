@@ -0,0 +1,331 @@
+use crate::Transaction;
+use sqlx::error::{DatabaseError, ErrorKind};
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+type Rows = Box<dyn Any + Send + Sync>;
+
+/// An in-memory database for service tests that should not need Postgres.
+///
+/// Repositories keep their rows in named tables of a `MemoryStore` through
+/// [`MemoryExecute`]. Like a `PgPool`, it is cheap to clone and every clone
+/// shares the same tables; `begin` on a repository holding one opens a
+/// [`Transaction`] that stages its writes until commit.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    tables: Arc<Mutex<HashMap<&'static str, Table>>>,
+}
+
+struct Table {
+    version: u64,
+    rows: Rows,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn begin(&self) -> MemoryTransaction {
+        MemoryTransaction {
+            store: self.clone(),
+            staged: HashMap::new(),
+        }
+    }
+
+    /// Runs `f` on the committed rows of `table`.
+    fn read_committed<K, V, T>(
+        &self,
+        table: &'static str,
+        f: impl FnOnce(&BTreeMap<K, V>) -> T,
+    ) -> T
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let tables = self.tables.lock().unwrap();
+        match tables.get(table) {
+            Some(committed) => f(downcast(table, &committed.rows)),
+            None => f(&BTreeMap::new()),
+        }
+    }
+
+    /// The committed rows of `table` and their version.
+    fn snapshot<K, V>(&self, table: &'static str) -> (u64, BTreeMap<K, V>)
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let tables = self.tables.lock().unwrap();
+        match tables.get(table) {
+            Some(committed) => (committed.version, downcast(table, &committed.rows).clone()),
+            None => (0, BTreeMap::new()),
+        }
+    }
+
+    fn write_committed<K, V, T>(
+        &self,
+        table: &'static str,
+        f: impl FnOnce(&mut BTreeMap<K, V>) -> T,
+    ) -> T
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let mut tables = self.tables.lock().unwrap();
+        let committed = tables.entry(table).or_insert_with(|| Table {
+            version: 0,
+            rows: Box::new(BTreeMap::<K, V>::new()),
+        });
+        committed.version += 1;
+        f(downcast_mut(table, &mut committed.rows))
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tables = self.tables.lock().unwrap();
+        f.debug_struct("MemoryStore")
+            .field("tables", &tables.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Writes of one in-memory transaction.
+///
+/// The first write to a table copies its committed rows; later reads and
+/// writes in the transaction see the copy. Commit publishes the copies and
+/// fails with a serialization failure if another transaction committed the
+/// same table in the meantime. Rollback simply drops them.
+pub struct MemoryTransaction {
+    store: MemoryStore,
+    staged: HashMap<&'static str, Staged>,
+}
+
+struct Staged {
+    base_version: u64,
+    rows: Rows,
+}
+
+impl MemoryTransaction {
+    fn read<K, V, T>(&self, table: &'static str, f: impl FnOnce(&BTreeMap<K, V>) -> T) -> T
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        match self.staged.get(table) {
+            Some(staged) => f(downcast(table, &staged.rows)),
+            None => self.store.read_committed(table, f),
+        }
+    }
+
+    fn write<K, V, T>(&mut self, table: &'static str, f: impl FnOnce(&mut BTreeMap<K, V>) -> T) -> T
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        let staged = self.staged.entry(table).or_insert_with(|| {
+            let (base_version, rows) = self.store.snapshot::<K, V>(table);
+            Staged {
+                base_version,
+                rows: Box::new(rows),
+            }
+        });
+        f(downcast_mut(table, &mut staged.rows))
+    }
+
+    pub(crate) fn commit(self) -> Result<(), sqlx::Error> {
+        let mut tables = self.store.tables.lock().unwrap();
+        for (table, staged) in &self.staged {
+            let version = tables.get(table).map_or(0, |committed| committed.version);
+            if version != staged.base_version {
+                return Err(MemoryError::serialization_failure(table).into());
+            }
+        }
+        for (table, staged) in self.staged {
+            tables.insert(
+                table,
+                Table {
+                    version: staged.base_version + 1,
+                    rows: staged.rows,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MemoryTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTransaction")
+            .field("staged", &self.staged.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn downcast<'a, K, V>(table: &str, rows: &'a Rows) -> &'a BTreeMap<K, V>
+where
+    K: 'static,
+    V: 'static,
+{
+    rows.downcast_ref()
+        .unwrap_or_else(|| panic!("table {table} used with another row type"))
+}
+
+fn downcast_mut<'a, K, V>(table: &str, rows: &'a mut Rows) -> &'a mut BTreeMap<K, V>
+where
+    K: 'static,
+    V: 'static,
+{
+    rows.downcast_mut()
+        .unwrap_or_else(|| panic!("table {table} used with another row type"))
+}
+
+/// Table access for in-memory repositories, implemented by [`MemoryStore`]
+/// (each write commits at once) and by in-memory [`Transaction`]s (writes are
+/// staged until commit).
+///
+/// Tables are ordered maps from a primary key to a row, created empty on first use.
+pub trait MemoryExecute {
+    fn read<K, V, T>(
+        &mut self,
+        table: &'static str,
+        f: impl FnOnce(&BTreeMap<K, V>) -> T,
+    ) -> Result<T, sqlx::Error>
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static;
+
+    fn write<K, V, T>(
+        &mut self,
+        table: &'static str,
+        f: impl FnOnce(&mut BTreeMap<K, V>) -> T,
+    ) -> Result<T, sqlx::Error>
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static;
+}
+
+impl MemoryExecute for MemoryStore {
+    fn read<K, V, T>(
+        &mut self,
+        table: &'static str,
+        f: impl FnOnce(&BTreeMap<K, V>) -> T,
+    ) -> Result<T, sqlx::Error>
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        Ok(self.read_committed(table, f))
+    }
+
+    fn write<K, V, T>(
+        &mut self,
+        table: &'static str,
+        f: impl FnOnce(&mut BTreeMap<K, V>) -> T,
+    ) -> Result<T, sqlx::Error>
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        Ok(self.write_committed(table, f))
+    }
+}
+
+impl MemoryExecute for Transaction<'_> {
+    fn read<K, V, T>(
+        &mut self,
+        table: &'static str,
+        f: impl FnOnce(&BTreeMap<K, V>) -> T,
+    ) -> Result<T, sqlx::Error>
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        Ok(self.memory()?.read(table, f))
+    }
+
+    fn write<K, V, T>(
+        &mut self,
+        table: &'static str,
+        f: impl FnOnce(&mut BTreeMap<K, V>) -> T,
+    ) -> Result<T, sqlx::Error>
+    where
+        K: Ord + Clone + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        Ok(self.memory()?.write(table, f))
+    }
+}
+
+/// Database error raised by in-memory repositories, carrying the SQLSTATE
+/// Postgres would have used so [`DbErrorKind`](crate::DbErrorKind) and
+/// [`ConstraintRegistry`](crate::ConstraintRegistry) treat both backends alike.
+#[derive(Debug)]
+pub struct MemoryError {
+    message: String,
+    code: &'static str,
+    constraint: Option<&'static str>,
+}
+
+impl MemoryError {
+    pub fn unique_violation(constraint: &'static str) -> Self {
+        Self {
+            message: format!("duplicate key value violates unique constraint \"{constraint}\""),
+            code: "23505",
+            constraint: Some(constraint),
+        }
+    }
+
+    pub fn serialization_failure(table: &str) -> Self {
+        Self {
+            message: format!("could not serialize access due to concurrent update of {table}"),
+            code: "40001",
+            constraint: None,
+        }
+    }
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+impl DatabaseError for MemoryError {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.code))
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn constraint(&self) -> Option<&str> {
+        self.constraint
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self.code {
+            "23505" => ErrorKind::UniqueViolation,
+            _ => ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Transaction metrics. Without the `metrics` feature [`TxMeter`] is a no-op.
+//!
+//! Every metric carries a `transaction` label holding [`TxContext::name`].
+
+use crate::{TxContext, TxError};
+
+#[cfg(feature = "metrics")]
+pub(crate) struct TxMeter {
+    transaction: String,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "metrics")]
+impl TxMeter {
+    pub(crate) fn begin(context: &TxContext) -> Self {
+        let transaction = context.name().to_string();
+        metrics::counter!("tx_chainable_transactions_started_total", "transaction" => transaction.clone())
+            .increment(1);
+        Self {
+            transaction,
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn chain(context: &TxContext) {
+        metrics::counter!("tx_chainable_chain_hops_total", "transaction" => context.name().to_string())
+            .increment(1);
+    }
+
+    pub(crate) fn rolled_back(&self) {
+        metrics::counter!("tx_chainable_transactions_rolled_back_total", "transaction" => self.transaction.clone())
+            .increment(1);
+    }
+
+    pub(crate) fn finish<T>(self, context: &TxContext, result: &Result<T, TxError>) {
+        let transaction = self.transaction;
+        match result {
+            Ok(_) if context.is_dry_run() => {
+                metrics::counter!("tx_chainable_transactions_rolled_back_total", "transaction" => transaction.clone())
+                    .increment(1);
+            }
+            Ok(_) => {
+                metrics::counter!("tx_chainable_transactions_committed_total", "transaction" => transaction.clone())
+                    .increment(1);
+            }
+            Err(e) => {
+                metrics::counter!(
+                    "tx_chainable_transactions_failed_total",
+                    "transaction" => transaction.clone(),
+                    "kind" => error_kind(e),
+                )
+                .increment(1);
+            }
+        }
+        metrics::histogram!("tx_chainable_transaction_duration_seconds", "transaction" => transaction.clone())
+            .record(self.start.elapsed().as_secs_f64());
+        metrics::histogram!("tx_chainable_chain_depth", "transaction" => transaction.clone())
+            .record(context.max_depth() as f64);
+        metrics::histogram!("tx_chainable_chain_hops", "transaction" => transaction)
+            .record(context.hops() as f64);
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn error_kind(error: &TxError) -> &'static str {
+    use crate::DbErrorKind;
+
+    match error {
+        TxError::Domain(_) => "domain",
+        TxError::BudgetExceeded(_) => "budget_exceeded",
+        TxError::ShuttingDown => "shutting_down",
+        TxError::Overloaded { .. } => "overloaded",
+        TxError::CircuitOpen => "circuit_open",
+        TxError::Escaped { .. } => "escaped",
+        TxError::Database(_) => match error.kind() {
+            Some(DbErrorKind::UniqueViolation { .. }) => "unique_violation",
+            Some(DbErrorKind::ForeignKeyViolation { .. }) => "foreign_key_violation",
+            Some(DbErrorKind::CheckViolation { .. }) => "check_violation",
+            Some(DbErrorKind::SerializationFailure) => "serialization_failure",
+            Some(DbErrorKind::Deadlock) => "deadlock",
+            Some(DbErrorKind::LockTimeout) => "lock_timeout",
+            Some(DbErrorKind::QueryCanceled) => "query_canceled",
+            Some(DbErrorKind::ConnectionLost) => "connection_lost",
+            None => "database",
+        },
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) struct TxMeter;
+
+#[cfg(not(feature = "metrics"))]
+impl TxMeter {
+    pub(crate) fn begin(_context: &TxContext) -> Self {
+        Self
+    }
+
+    pub(crate) fn chain(_context: &TxContext) {}
+
+    pub(crate) fn rolled_back(&self) {}
+
+    pub(crate) fn finish<T>(self, _context: &TxContext, _result: &Result<T, TxError>) {}
+}
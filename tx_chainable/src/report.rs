@@ -0,0 +1,93 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What [`Begin::begin_with_report`](crate::Begin::begin_with_report) observed
+/// while the transaction held its connection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TxReport {
+    pub statements: Vec<StatementReport>,
+    pub chain_hops: usize,
+    /// Time from BEGIN until COMMIT or the closure failing.
+    pub connection_held: Duration,
+    /// WAL written between BEGIN and COMMIT, from the `pg_current_wal_insert_lsn`
+    /// delta. The delta is server-wide, so it includes concurrent writers.
+    /// `None` on a standby or when the closure failed.
+    pub wal_bytes: Option<u64>,
+}
+
+/// A statement executed through `Execute::execute`.
+///
+/// Bound parameter values are only recorded with
+/// [`TxOptions::report_parameter_values`](crate::TxOptions::report_parameter_values).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementReport {
+    /// The repository holding the transaction when the statement ran.
+    pub repository: &'static str,
+    pub sql: String,
+    pub parameters: usize,
+    /// The bound values in text, `None` for SQL NULL. `None` unless requested.
+    pub parameter_values: Option<Vec<Option<String>>>,
+    /// Rows inserted, updated or deleted; always 0 for reads.
+    pub rows_affected: u64,
+    pub rows_returned: u64,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Recording {
+    parameter_values: bool,
+    statements: Mutex<Vec<StatementReport>>,
+    wal_start: Mutex<Option<String>>,
+    wal_bytes: Mutex<Option<u64>>,
+}
+
+impl Recording {
+    pub(crate) fn new(parameter_values: bool) -> Self {
+        Self {
+            parameter_values,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn parameter_values(&self) -> bool {
+        self.parameter_values
+    }
+
+    pub(crate) fn statement(&self, statement: StatementReport) {
+        self.statements.lock().unwrap().push(statement);
+    }
+
+    pub(crate) async fn wal_start(&self, conn: &mut sqlx::PgConnection) -> Result<(), sqlx::Error> {
+        let lsn = sqlx::query_scalar(
+            "SELECT CASE WHEN pg_is_in_recovery() THEN NULL \
+             ELSE pg_current_wal_insert_lsn()::text END",
+        )
+        .fetch_one(conn)
+        .await?;
+        *self.wal_start.lock().unwrap() = lsn;
+        Ok(())
+    }
+
+    pub(crate) async fn wal_end(&self, conn: &mut sqlx::PgConnection) -> Result<(), sqlx::Error> {
+        let Some(start) = self.wal_start.lock().unwrap().clone() else {
+            return Ok(());
+        };
+        let bytes: i64 = sqlx::query_scalar(
+            "SELECT pg_wal_lsn_diff(pg_current_wal_insert_lsn(), $1::pg_lsn)::bigint",
+        )
+        .bind(start)
+        .fetch_one(conn)
+        .await?;
+        *self.wal_bytes.lock().unwrap() = Some(bytes.max(0) as u64);
+        Ok(())
+    }
+
+    pub(crate) fn report(&self, chain_hops: usize, connection_held: Duration) -> TxReport {
+        TxReport {
+            statements: std::mem::take(&mut *self.statements.lock().unwrap()),
+            chain_hops,
+            connection_held,
+            wal_bytes: *self.wal_bytes.lock().unwrap(),
+        }
+    }
+}
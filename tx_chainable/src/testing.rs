@@ -0,0 +1,91 @@
+//! Rollback-only test mode behind the `test` feature.
+//!
+//! [`rollback_only`] returns a pool with a single connection held inside an
+//! outer transaction that is never committed. sqlx turns a `begin` on a
+//! connection already in a transaction into a savepoint, so every
+//! [`Begin::begin`](crate::Begin::begin) in a test commits or rolls back its
+//! savepoint only, and [`roll_back`] discards everything at the end. Tests can
+//! then share one database instead of creating a fresh one each.
+//!
+//! The single connection serializes the test: a `begin` inside another
+//! `begin`'s closure, or a pool query there, waits for the pool's acquire
+//! timeout and fails.
+
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgTransactionManager};
+use sqlx::{PgPool, TransactionManager};
+use std::future::Future;
+
+/// Values `#[tx_chainable::test]` can pass to a test, typically repositories
+/// over the rollback-only pool.
+pub trait FromTestPool {
+    fn from_test_pool(pool: &PgPool) -> Self;
+}
+
+impl FromTestPool for PgPool {
+    fn from_test_pool(pool: &PgPool) -> Self {
+        pool.clone()
+    }
+}
+
+/// Connects a single-connection pool whose connection is always inside an
+/// outer transaction.
+pub async fn rollback_only(options: PgConnectOptions) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        // A replacement connection starts its own outer transaction
+        .after_connect(|conn, _| PgTransactionManager::begin(conn, None))
+        .connect_with(options)
+        .await
+}
+
+/// Rolls back the outer transaction of a [`rollback_only`] pool and closes it.
+pub async fn roll_back(pool: PgPool) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    while PgTransactionManager::get_transaction_depth(&conn) > 0 {
+        PgTransactionManager::rollback(&mut conn).await?;
+    }
+    drop(conn);
+    pool.close().await;
+    Ok(())
+}
+
+/// Runs the body of a `#[tx_chainable::test]`.
+#[doc(hidden)]
+pub fn run<F, Fut, T>(migrator: Option<Migrator>, f: F) -> T
+where
+    F: FnOnce(PgPool) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let url =
+        std::env::var("DATABASE_URL").expect("DATABASE_URL must be set for #[tx_chainable::test]");
+    let options: PgConnectOptions = url
+        .parse()
+        .expect("DATABASE_URL is not a valid Postgres URL");
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build the test runtime")
+        .block_on(async move {
+            if let Some(migrator) = migrator {
+                let pool = PgPool::connect_with(options.clone())
+                    .await
+                    .expect("failed to connect to DATABASE_URL");
+                migrator
+                    .run(&pool)
+                    .await
+                    .expect("failed to apply migrations");
+                pool.close().await;
+            }
+            let pool = rollback_only(options)
+                .await
+                .expect("failed to connect to DATABASE_URL");
+            let result = f(pool.clone()).await;
+            roll_back(pool)
+                .await
+                .expect("failed to roll back the test transaction");
+            result
+        })
+}
@@ -0,0 +1,109 @@
+//! Spans around `begin` and `chain`. Without the `tracing` feature [`TxSpan`]
+//! is a no-op.
+
+use crate::TxError;
+use std::future::Future;
+
+/// `my_crate::repositories::EventsRepository` for
+/// `my_crate::repositories::EventsRepository<PgPool>`.
+pub(crate) fn type_path<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.split('<').next().unwrap_or(name)
+}
+
+/// `EventsRepository` for `my_crate::repositories::EventsRepository`.
+pub(crate) fn short_name(path: &'static str) -> &'static str {
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) struct TxSpan {
+    span: tracing::Span,
+    start: std::time::Instant,
+    ok: &'static str,
+    err: &'static str,
+}
+
+#[cfg(feature = "tracing")]
+impl TxSpan {
+    pub(crate) fn begin(repository: &'static str, dry_run: bool) -> Self {
+        let span = tracing::info_span!(
+            "tx.begin",
+            repository,
+            dry_run,
+            outcome = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let ok = if dry_run { "rolled_back" } else { "committed" };
+        Self::new(span, ok, "rolled_back")
+    }
+
+    pub(crate) fn chain(source: &'static str, target: &'static str) -> Self {
+        let span = tracing::info_span!(
+            "tx.chain",
+            otel.name = %format!("{source} -> {target}"),
+            outcome = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        Self::new(span, "ok", "error")
+    }
+
+    fn new(span: tracing::Span, ok: &'static str, err: &'static str) -> Self {
+        Self {
+            span,
+            start: std::time::Instant::now(),
+            ok,
+            err,
+        }
+    }
+
+    /// Runs synchronous work, such as building the closure's future, in the span.
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.span.in_scope(f)
+    }
+
+    pub(crate) async fn scope<F: Future>(&self, fut: F) -> F::Output {
+        use tracing::Instrument;
+        fut.instrument(self.span.clone()).await
+    }
+
+    pub(crate) fn finish<T>(self, result: &Result<T, TxError>) {
+        let duration_ms = self.start.elapsed().as_secs_f64() * 1000.0;
+        self.span.record("duration_ms", duration_ms);
+        match result {
+            Ok(_) => {
+                self.span.record("outcome", self.ok);
+            }
+            Err(e) => {
+                self.span.record("outcome", self.err);
+                self.span.record("error", tracing::field::display(e));
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct TxSpan;
+
+#[cfg(not(feature = "tracing"))]
+impl TxSpan {
+    pub(crate) fn begin(_repository: &'static str, _dry_run: bool) -> Self {
+        Self
+    }
+
+    pub(crate) fn chain(_source: &'static str, _target: &'static str) -> Self {
+        Self
+    }
+
+    pub(crate) fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        f()
+    }
+
+    pub(crate) async fn scope<F: Future>(&self, fut: F) -> F::Output {
+        fut.await
+    }
+
+    pub(crate) fn finish<T>(self, _result: &Result<T, TxError>) {}
+}
@@ -0,0 +1,634 @@
+use crate::budget::BudgetTracker;
+use crate::executor::TxConnection;
+use crate::fault::FaultState;
+#[cfg(feature = "test")]
+use crate::interleave::Turns;
+use crate::memory::MemoryTransaction;
+use crate::report::Recording;
+use crate::trace;
+use crate::{
+    BoxFuture, BudgetExceeded, EscapeAction, EscapeDetector, Execute, InFlightRegistry,
+    MemoryStore, TxBudget,
+};
+use sqlx::{Acquire, PgConnection, PgPool, PgTransaction, Postgres};
+use std::borrow::Cow;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Options supplied to [`Begin::begin_with`](crate::Begin::begin_with).
+#[derive(Debug, Clone, Default)]
+pub struct TxOptions {
+    name: Option<Cow<'static, str>>,
+    tag_queries: bool,
+    traceparent: Option<String>,
+    budget: Option<TxBudget>,
+    isolation: Option<IsolationLevel>,
+    #[cfg(feature = "test")]
+    turns: Option<Arc<Turns>>,
+    report_parameter_values: bool,
+    on_escape: EscapeAction,
+}
+
+/// Isolation level of a Postgres transaction, ordered from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    pub const ALL: [Self; 3] = [
+        Self::ReadCommitted,
+        Self::RepeatableRead,
+        Self::Serializable,
+    ];
+
+    fn as_sql(self) -> &'static str {
+        match self {
+            Self::ReadCommitted => "READ COMMITTED",
+            Self::RepeatableRead => "REPEATABLE READ",
+            Self::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+impl std::fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_sql())
+    }
+}
+
+impl TxOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names the transaction in metrics. Defaults to the starting repository's type name.
+    pub fn name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Appends a sqlcommenter-style comment to every statement and sets
+    /// `application_name` to the transaction name until the transaction ends.
+    ///
+    /// Tagged statements are not added to the connection's statement cache,
+    /// since the comment changes with the chain path.
+    pub fn tag_queries(mut self, enabled: bool) -> Self {
+        self.tag_queries = enabled;
+        self
+    }
+
+    /// W3C `traceparent` included in query tags.
+    pub fn traceparent(mut self, traceparent: impl Into<String>) -> Self {
+        self.traceparent = Some(traceparent.into());
+        self
+    }
+
+    /// Limits the statements, rows and connection time of the transaction.
+    pub fn budget(mut self, budget: TxBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Includes bound parameter values, as Postgres renders them in text, in
+    /// the statements of a [`TxReport`](crate::TxReport). Off by default, since
+    /// values may hold personal data and reading them costs a round trip per
+    /// statement.
+    pub fn report_parameter_values(mut self, enabled: bool) -> Self {
+        self.report_parameter_values = enabled;
+        self
+    }
+
+    /// What a statement escaping the transaction, e.g. one run on the pool
+    /// inside the closure, does in debug builds. Defaults to
+    /// [`EscapeAction::Warn`]; see [`EscapeDetector`](crate::EscapeDetector).
+    pub fn on_escape(mut self, action: EscapeAction) -> Self {
+        self.on_escape = action;
+        self
+    }
+
+    /// Runs the transaction at `isolation` instead of the server default.
+    /// Ignored by in-memory transactions.
+    pub fn isolation(mut self, isolation: IsolationLevel) -> Self {
+        self.isolation = Some(isolation);
+        self
+    }
+
+    /// Runs statements only in the transaction's turns of an
+    /// [`Interleaving`](crate::Interleaving), usually set through
+    /// [`Schedule::options`](crate::Schedule::options).
+    #[cfg(feature = "test")]
+    pub fn turns(mut self, turns: Turns) -> Self {
+        self.turns = Some(Arc::new(turns));
+        self
+    }
+
+    /// `type_path` is the [`Tx::name`](crate::Tx::name) of the repository
+    /// calling `begin`.
+    pub(crate) fn into_context(self, type_path: &'static str, record: bool) -> TxContext {
+        let repository = trace::short_name(type_path);
+        TxContext {
+            name: self.name.unwrap_or(Cow::Borrowed(repository)),
+            type_path,
+            tag_queries: self.tag_queries,
+            traceparent: self.traceparent,
+            path: Mutex::new(vec![repository]),
+            max_depth: AtomicUsize::new(0),
+            hops: AtomicUsize::new(0),
+            recording: record.then(|| Recording::new(self.report_parameter_values)),
+            budget: self.budget.map(BudgetTracker::new),
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            connected_at: OnceLock::new(),
+            backend_pid: OnceLock::new(),
+            last_statement: Mutex::new(None),
+            dry_run: false,
+            faults: OnceLock::new(),
+            isolation: self.isolation,
+            #[cfg(feature = "test")]
+            turns: self.turns,
+            escape_action: self.on_escape,
+        }
+    }
+}
+
+/// State shared by every repository taking part in one transaction.
+#[derive(Debug)]
+pub struct TxContext {
+    name: Cow<'static, str>,
+    type_path: &'static str,
+    tag_queries: bool,
+    traceparent: Option<String>,
+    path: Mutex<Vec<&'static str>>,
+    max_depth: AtomicUsize,
+    hops: AtomicUsize,
+    recording: Option<Recording>,
+    budget: Option<BudgetTracker>,
+    started_at: SystemTime,
+    started: Instant,
+    connected_at: OnceLock<Instant>,
+    backend_pid: OnceLock<i32>,
+    last_statement: Mutex<Option<String>>,
+    dry_run: bool,
+    faults: OnceLock<FaultState>,
+    isolation: Option<IsolationLevel>,
+    #[cfg(feature = "test")]
+    turns: Option<Arc<Turns>>,
+    escape_action: EscapeAction,
+}
+
+impl TxContext {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The [`Tx::name`](crate::Tx::name) of the repository that began the
+    /// transaction.
+    pub(crate) fn type_path(&self) -> &'static str {
+        self.type_path
+    }
+
+    /// The repository currently holding the transaction.
+    pub fn repository(&self) -> &'static str {
+        let path = self.path.lock().unwrap();
+        path.last().copied().unwrap_or_default()
+    }
+
+    /// Repositories from the one that began the transaction to the current one,
+    /// e.g. `EventsRepository -> UsersRepository`.
+    pub fn chain_path(&self) -> String {
+        self.path.lock().unwrap().join(" -> ")
+    }
+
+    /// How many `chain` calls are currently nested.
+    pub fn depth(&self) -> usize {
+        self.path.lock().unwrap().len().saturating_sub(1)
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    /// How many times the transaction has been handed to another repository.
+    pub fn hops(&self) -> usize {
+        self.hops.load(Ordering::Relaxed)
+    }
+
+    /// When `begin` was called.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    pub fn age(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// PID of the backend serving the transaction, known while the
+    /// [`InFlightRegistry`] is enabled.
+    pub fn backend_pid(&self) -> Option<i32> {
+        self.backend_pid.get().copied()
+    }
+
+    /// SQL of the most recent statement, known while the [`InFlightRegistry`] is enabled.
+    pub fn last_statement(&self) -> Option<String> {
+        self.last_statement.lock().unwrap().clone()
+    }
+
+    /// Whether the transaction was begun by
+    /// [`Begin::begin_dry_run`](crate::Begin::begin_dry_run) and will be rolled
+    /// back. Repositories should skip side effects such as outbox enqueues.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub(crate) fn into_dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    pub(crate) fn inject_faults(&self, faults: FaultState) {
+        let _ = self.faults.set(faults);
+    }
+
+    /// Applies injected faults due before a statement runs on `conn`.
+    pub(crate) async fn fault_statement(&self, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+        match self.faults.get() {
+            Some(faults) => faults.statement(conn).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `statement` in the transaction's next turn, if it is interleaved.
+    pub(crate) async fn turn<T>(&self, statement: impl Future<Output = T>) -> T {
+        #[cfg(feature = "test")]
+        if let Some(turns) = &self.turns {
+            return turns.take(statement).await;
+        }
+        statement.await
+    }
+
+    pub(crate) fn statement_started(&self, sql: &str) {
+        if InFlightRegistry::is_enabled() {
+            *self.last_statement.lock().unwrap() = Some(sql.to_string());
+        }
+    }
+
+    /// Set with [`TxOptions::on_escape`].
+    pub fn escape_action(&self) -> EscapeAction {
+        self.escape_action
+    }
+
+    pub(crate) fn tags_queries(&self) -> bool {
+        self.tag_queries
+    }
+
+    /// Whether statements have to go through [`TxConnection`]'s rewriting path.
+    pub(crate) fn intercepts(&self) -> bool {
+        #[cfg(feature = "test")]
+        let interleaved = self.turns.is_some();
+        #[cfg(not(feature = "test"))]
+        let interleaved = false;
+        self.tag_queries
+            || self.recording.is_some()
+            || self.budget.is_some()
+            || self.faults.get().is_some()
+            || interleaved
+    }
+
+    /// Counts a statement that is about to run against the budget.
+    pub(crate) fn budget_statement(&self) -> Result<(), BudgetExceeded> {
+        match &self.budget {
+            Some(budget) => budget.statement(self.repository(), self.connection_held()),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the rows a statement returned against the budget.
+    pub(crate) fn budget_rows(&self, rows: u64) -> Result<(), BudgetExceeded> {
+        match &self.budget {
+            Some(budget) => budget.rows(self.repository(), rows),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    /// Time since BEGIN completed.
+    pub(crate) fn connection_held(&self) -> Duration {
+        self.connected_at
+            .get()
+            .map(Instant::elapsed)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn enter_chain(&self, repository: &'static str) {
+        let depth = {
+            let mut path = self.path.lock().unwrap();
+            path.push(repository);
+            path.len() - 1
+        };
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+        self.hops.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn exit_chain(&self) {
+        self.path.lock().unwrap().pop();
+    }
+
+    /// Sqlcommenter comment for the next statement, if query tagging is enabled.
+    pub(crate) fn sql_comment(&self) -> Option<String> {
+        if !self.tag_queries {
+            return None;
+        }
+        let chain = self.chain_path();
+        // sqlcommenter requires keys in lexicographic order
+        let mut tags = vec![
+            ("chain", chain.as_str()),
+            ("repository", self.repository()),
+            ("transaction", self.name()),
+        ];
+        if let Some(traceparent) = &self.traceparent {
+            tags.insert(2, ("traceparent", traceparent.as_str()));
+        }
+        let tags = tags
+            .into_iter()
+            .map(|(key, value)| format!("{key}='{}'", url_encode(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        Some(format!("/*{tags}*/"))
+    }
+}
+
+fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// A transaction carrying the [`TxContext`] of the `begin` that opened it.
+///
+/// This is the executor of every `Tx::TxRepository`. It runs on Postgres, or
+/// on a [`MemoryStore`] for repositories implementing
+/// [`MemoryExecute`](crate::MemoryExecute).
+#[derive(Debug)]
+pub struct Transaction<'tx> {
+    inner: Backend<'tx>,
+    context: Arc<TxContext>,
+    unfinished: Option<Arc<Unfinished<'tx>>>,
+}
+
+#[derive(Debug)]
+enum Backend<'tx> {
+    Postgres(PgTransaction<'tx>),
+    Memory(MemoryTransaction),
+    /// Committed or rolled back.
+    Finished,
+}
+
+/// Where a [`Transaction`] dropped before it finished leaves its Postgres
+/// transaction, so `begin` can roll it back rather than leave that to the
+/// pool.
+#[derive(Debug, Default)]
+pub(crate) struct Unfinished<'tx>(Mutex<Option<PgTransaction<'tx>>>);
+
+impl Unfinished<'_> {
+    /// Rolls back the transaction left here, if any.
+    pub(crate) async fn rollback(&self) {
+        let inner = self.0.lock().unwrap().take();
+        if let Some(inner) = inner {
+            // A failed ROLLBACK closes the connection, which ends it anyway
+            let _ = inner.rollback().await;
+        }
+    }
+}
+
+impl<'tx> Transaction<'tx> {
+    /// Wraps a transaction that just ran BEGIN.
+    pub(crate) fn begun(inner: PgTransaction<'tx>, context: Arc<TxContext>) -> Self {
+        context.connected_at.get_or_init(Instant::now);
+        Self {
+            inner: Backend::Postgres(inner),
+            context,
+            unfinished: None,
+        }
+    }
+
+    /// Runs the statements the context asks for before any of `f`'s.
+    pub(crate) async fn set_up(&mut self) -> Result<(), sqlx::Error> {
+        let (Backend::Postgres(inner), context) = (&mut self.inner, &self.context) else {
+            return Ok(());
+        };
+        // Must precede every other statement
+        if let Some(isolation) = context.isolation {
+            let sql = format!("SET TRANSACTION ISOLATION LEVEL {}", isolation.as_sql());
+            sqlx::query(&sql).execute(inner.as_mut()).await?;
+        }
+        if InFlightRegistry::is_enabled() {
+            let pid = sqlx::query_scalar("SELECT pg_backend_pid()")
+                .fetch_one(inner.as_mut())
+                .await?;
+            context.backend_pid.get_or_init(|| pid);
+        }
+        if let Some(recording) = context.recording() {
+            recording.wal_start(inner.as_mut()).await?;
+        }
+        if context.tags_queries() {
+            sqlx::query("SELECT set_config('application_name', $1, true)")
+                .bind(context.name())
+                .execute(inner.as_mut())
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn start_memory(inner: MemoryTransaction, context: Arc<TxContext>) -> Self {
+        context.connected_at.get_or_init(Instant::now);
+        Self {
+            inner: Backend::Memory(inner),
+            context,
+            unfinished: None,
+        }
+    }
+
+    pub fn context(&self) -> &TxContext {
+        &self.context
+    }
+
+    /// Sends `payload` on `channel` through `pg_notify`. Postgres delivers it
+    /// when the transaction commits, and drops it if it rolls back.
+    pub async fn notify(&mut self, channel: &str, payload: &str) -> Result<(), sqlx::Error> {
+        self.execute(|e| {
+            sqlx::query("SELECT pg_notify($1, $2)")
+                .bind(channel)
+                .bind(payload)
+                .execute(e)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Leaves the transaction in `unfinished` if it is dropped before it
+    /// commits or rolls back.
+    pub(crate) fn on_drop(&mut self, unfinished: Arc<Unfinished<'tx>>) {
+        self.unfinished = Some(unfinished);
+    }
+
+    pub(crate) fn shared_context(&self) -> Arc<TxContext> {
+        self.context.clone()
+    }
+
+    pub(crate) fn memory(&mut self) -> Result<&mut MemoryTransaction, sqlx::Error> {
+        match &mut self.inner {
+            Backend::Memory(inner) => Ok(inner),
+            Backend::Postgres(_) | Backend::Finished => Err(sqlx::Error::Configuration(
+                "in-memory repository used in a Postgres transaction".into(),
+            )),
+        }
+    }
+
+    pub(crate) async fn commit(mut self) -> Result<(), sqlx::Error> {
+        if let Some(faults) = self.context.faults.get() {
+            // Dropping the transaction rolls it back
+            faults.commit()?;
+        }
+        match self.finish() {
+            Backend::Postgres(mut inner) => {
+                if let Some(recording) = self.context.recording() {
+                    recording.wal_end(inner.as_mut()).await?;
+                }
+                self.context.turn(inner.commit()).await
+            }
+            Backend::Memory(inner) => inner.commit(),
+            Backend::Finished => Ok(()),
+        }
+    }
+
+    pub(crate) async fn rollback(mut self) -> Result<(), sqlx::Error> {
+        match self.finish() {
+            Backend::Postgres(mut inner) => {
+                if let Some(recording) = self.context.recording() {
+                    recording.wal_end(inner.as_mut()).await?;
+                }
+                inner.rollback().await
+            }
+            // Staged writes are simply dropped
+            Backend::Memory(_) | Backend::Finished => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Backend<'tx> {
+        std::mem::replace(&mut self.inner, Backend::Finished)
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if let Some(unfinished) = self.unfinished.take() {
+            if let Backend::Postgres(inner) = self.finish() {
+                *unfinished.0.lock().unwrap() = Some(inner);
+            }
+        }
+    }
+}
+
+/// Where `begin` opens a [`Transaction`]: a `&PgPool`, a `&mut PgConnection`,
+/// or a [`MemoryStore`].
+///
+/// This is the bound on [`GetExecutor::Executor`](crate::GetExecutor::Executor)
+/// for repositories implementing [`Begin`](crate::Begin).
+pub trait TxSource<'tx> {
+    /// Acquires a connection and runs BEGIN. Through a
+    /// [`CircuitBreaker`](crate::CircuitBreaker), only this counts towards
+    /// opening the circuit.
+    fn begin_transaction(
+        self,
+        context: Arc<TxContext>,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, sqlx::Error>>;
+
+    /// The pool connections are acquired from, if any, which the
+    /// [`CircuitBreaker`](crate::CircuitBreaker) looks at to tell an
+    /// exhausted pool from one that cannot connect.
+    fn pool(&self) -> Option<&'tx PgPool> {
+        None
+    }
+}
+
+impl<'tx> TxSource<'tx> for &'tx PgPool {
+    fn begin_transaction(
+        self,
+        context: Arc<TxContext>,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, sqlx::Error>> {
+        begin_on(self, context)
+    }
+
+    fn pool(&self) -> Option<&'tx PgPool> {
+        Some(self)
+    }
+}
+
+impl<'tx> TxSource<'tx> for &'tx mut PgConnection {
+    fn begin_transaction(
+        self,
+        context: Arc<TxContext>,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, sqlx::Error>> {
+        begin_on(self, context)
+    }
+}
+
+fn begin_on<'tx, A>(
+    source: A,
+    context: Arc<TxContext>,
+) -> BoxFuture<'tx, Result<Transaction<'tx>, sqlx::Error>>
+where
+    A: Acquire<'tx, Database = Postgres> + Send + 'tx,
+{
+    EscapeDetector::check("begin");
+    let begin = source.begin();
+    Box::pin(async move { Ok(Transaction::begun(begin.await?, context)) })
+}
+
+impl<'tx> TxSource<'tx> for &'tx MemoryStore {
+    fn begin_transaction(
+        self,
+        context: Arc<TxContext>,
+    ) -> BoxFuture<'tx, Result<Transaction<'tx>, sqlx::Error>> {
+        let inner = self.begin();
+        Box::pin(async move { Ok(Transaction::start_memory(inner, context)) })
+    }
+}
+
+impl<'t> Execute for Transaction<'t> {
+    type Executor<'tx> = TxConnection<'tx>;
+
+    fn execute<'tx, F, Fut, T>(&'tx mut self, f: F) -> Fut
+    where
+        F: FnOnce(Self::Executor<'tx>) -> Fut,
+        Fut: Future<Output = T> + Send,
+        T: Send,
+    {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            executor = "transaction",
+            transaction = self.context.name(),
+            "execute"
+        );
+        let conn = match &mut self.inner {
+            Backend::Postgres(inner) => Some(inner.as_mut()),
+            Backend::Memory(_) | Backend::Finished => None,
+        };
+        f(TxConnection::new(conn, &self.context))
+    }
+}
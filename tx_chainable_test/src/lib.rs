@@ -0,0 +1,77 @@
+//! The `#[tx_chainable::test]` attribute. Use it through the `test` feature of
+//! `tx-chainable`, which re-exports it together with its runtime support.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, FnArg, ItemFn, LitStr};
+
+/// Runs an async test in one Postgres transaction that is always rolled back.
+///
+/// Every parameter is built from the test pool with
+/// `tx_chainable::testing::FromTestPool`. The pool has a single connection
+/// held inside the outer transaction, so each `begin` in the test runs as a
+/// savepoint. `DATABASE_URL` names the database, and
+/// `migrations = "./migrations"` applies migrations to it before the test.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut migrations: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("migrations") {
+            migrations = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `migrations = \"<path>\"`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+    let input = parse_macro_input!(item as ItemFn);
+
+    match expand(input, migrations) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(input: ItemFn, migrations: Option<LitStr>) -> syn::Result<proc_macro2::TokenStream> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = input;
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            "#[tx_chainable::test] functions must be async",
+        ));
+    }
+    for input in &sig.inputs {
+        if let FnArg::Receiver(receiver) = input {
+            return Err(syn::Error::new_spanned(
+                receiver,
+                "#[tx_chainable::test] functions cannot take self",
+            ));
+        }
+    }
+
+    let name = &sig.ident;
+    let output = &sig.output;
+    let inputs = &sig.inputs;
+    let arguments = sig.inputs.iter().map(|_| {
+        quote! { ::tx_chainable::testing::FromTestPool::from_test_pool(&pool) }
+    });
+    let migrator = match migrations {
+        Some(path) => quote! { ::core::option::Option::Some(::sqlx::migrate!(#path)) },
+        None => quote! { ::core::option::Option::None },
+    };
+
+    Ok(quote! {
+        #[::core::prelude::v1::test]
+        #(#attrs)*
+        #vis fn #name() #output {
+            async fn test_body(#inputs) #output #block
+
+            ::tx_chainable::testing::run(#migrator, |pool| test_body(#(#arguments),*))
+        }
+    })
+}